@@ -1,55 +1,623 @@
 use itertools::Itertools;
-use num::abs;
-use std::fs;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::io::Write;
+use std::{fs, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    X,
+    Y,
+}
+
+impl Register {
+    fn from_string(string: &str) -> Register {
+        match string {
+            "x" => Register::X,
+            "y" => Register::Y,
+            _ => panic!("Unknown register {}", string),
+        }
+    }
+}
+
+// Where execution continues once an instruction has completed.
+enum Flow {
+    Next,
+    Jump(isize),
+}
+
+// An instruction takes `cycles` cycles and only changes the CPU when its last cycle ends.
+trait Instruction: Debug {
+    fn cycles(&self) -> usize;
+    fn execute(&self, cpu: &mut Cpu) -> Flow;
+}
+
+#[derive(Debug)]
+struct Noop;
+
+impl Instruction for Noop {
+    fn cycles(&self) -> usize {
+        1
+    }
+
+    fn execute(&self, _cpu: &mut Cpu) -> Flow {
+        Flow::Next
+    }
+}
+
+#[derive(Debug)]
+struct Add(Register, i32);
+
+impl Instruction for Add {
+    fn cycles(&self) -> usize {
+        2
+    }
+
+    fn execute(&self, cpu: &mut Cpu) -> Flow {
+        *cpu.register_mut(self.0) += self.1;
+        Flow::Next
+    }
+}
+
+#[derive(Debug)]
+struct Mul(Register, i32);
+
+impl Instruction for Mul {
+    fn cycles(&self) -> usize {
+        3
+    }
+
+    fn execute(&self, cpu: &mut Cpu) -> Flow {
+        *cpu.register_mut(self.0) *= self.1;
+        Flow::Next
+    }
+}
+
+#[derive(Debug)]
+struct Jump(isize);
+
+impl Instruction for Jump {
+    fn cycles(&self) -> usize {
+        1
+    }
+
+    fn execute(&self, _cpu: &mut Cpu) -> Flow {
+        Flow::Jump(self.0)
+    }
+}
+
+// Jumps by `offset` when `register` is zero, or when it is non-zero if `if_zero` is false.
+#[derive(Debug)]
+struct JumpIf {
+    register: Register,
+    if_zero: bool,
+    offset: isize,
+}
+
+impl Instruction for JumpIf {
+    fn cycles(&self) -> usize {
+        2
+    }
+
+    fn execute(&self, cpu: &mut Cpu) -> Flow {
+        if (cpu.register(self.register) == 0) == self.if_zero {
+            Flow::Jump(self.offset)
+        } else {
+            Flow::Next
+        }
+    }
+}
+
+type Program = Vec<Box<dyn Instruction>>;
+
+type InstructionParser = fn(&[&str]) -> Box<dyn Instruction>;
+
+struct InstructionSet {
+    parsers: HashMap<&'static str, InstructionParser>,
+}
+
+impl InstructionSet {
+    fn new() -> InstructionSet {
+        InstructionSet {
+            parsers: HashMap::new(),
+        }
+    }
+
+    fn with(mut self, name: &'static str, parser: InstructionParser) -> InstructionSet {
+        self.parsers.insert(name, parser);
+        self
+    }
+
+    // The instructions of the puzzle.
+    fn standard() -> InstructionSet {
+        InstructionSet::new()
+            .with("noop", |_| Box::new(Noop))
+            .with("addx", |args| {
+                Box::new(Add(Register::X, args[0].parse().unwrap()))
+            })
+    }
+
+    fn extended() -> InstructionSet {
+        InstructionSet::standard()
+            .with("addy", |args| {
+                Box::new(Add(Register::Y, args[0].parse().unwrap()))
+            })
+            .with("mulx", |args| {
+                Box::new(Mul(Register::X, args[0].parse().unwrap()))
+            })
+            .with("jmp", |args| Box::new(Jump(args[0].parse().unwrap())))
+            .with("jz", |args| {
+                Box::new(JumpIf {
+                    register: Register::from_string(args[0]),
+                    if_zero: true,
+                    offset: args[1].parse().unwrap(),
+                })
+            })
+            .with("jnz", |args| {
+                Box::new(JumpIf {
+                    register: Register::from_string(args[0]),
+                    if_zero: false,
+                    offset: args[1].parse().unwrap(),
+                })
+            })
+    }
+
+    fn parse(&self, text: &str) -> Program {
+        text.split('\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let words = line.split(' ').collect_vec();
+                let parser = self
+                    .parsers
+                    .get(words[0])
+                    .unwrap_or_else(|| panic!("Unknown instruction {}", words[0]));
+                parser(&words[1..])
+            })
+            .collect_vec()
+    }
+}
+
+// The state of the CPU during one cycle, before the instruction finishing in it takes effect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tick {
+    cycle: usize,
+    pc: usize,
+    registers: [i32; 2],
+}
+
+impl Tick {
+    fn x(&self) -> i32 {
+        self.registers[Register::X as usize]
+    }
+}
 
 #[derive(Debug, Clone)]
-enum Operation {
-    Noop,
-    Addx(i32),
+struct Cpu {
+    registers: [i32; 2],
+    pc: usize,
+    cycle: usize,
+    // Cycles left for the instruction at `pc`, once it has started.
+    remaining_cycles: Option<usize>,
 }
 
-fn read_input() -> Vec<Operation> {
-    let file_contents = fs::read_to_string("day10_puzzle.txt").expect("Unable to read file");
-    file_contents
-        .split("\n")
-        .map(|line| {
-            if line == "noop" {
-                Operation::Noop
-            } else {
-                let x = line.split_once(" ").unwrap().1.parse().unwrap();
-                Operation::Addx(x)
-            }
+impl Cpu {
+    fn new() -> Cpu {
+        Cpu {
+            registers: [1, 0],
+            pc: 0,
+            cycle: 0,
+            remaining_cycles: None,
+        }
+    }
+
+    fn register(&self, register: Register) -> i32 {
+        self.registers[register as usize]
+    }
+
+    fn register_mut(&mut self, register: Register) -> &mut i32 {
+        &mut self.registers[register as usize]
+    }
+
+    fn halted(&self, program: &Program) -> bool {
+        self.pc >= program.len()
+    }
+
+    // Runs one cycle, or returns None once the program counter has left the program.
+    fn tick(&mut self, program: &Program) -> Option<Tick> {
+        if self.halted(program) {
+            return None;
+        }
+        let instruction = &program[self.pc];
+        self.cycle += 1;
+        let tick = Tick {
+            cycle: self.cycle,
+            pc: self.pc,
+            registers: self.registers,
+        };
+        let remaining_cycles = self.remaining_cycles.unwrap_or(instruction.cycles()) - 1;
+        if remaining_cycles > 0 {
+            self.remaining_cycles = Some(remaining_cycles);
+        } else {
+            self.remaining_cycles = None;
+            self.pc = match instruction.execute(self) {
+                Flow::Next => self.pc + 1,
+                Flow::Jump(offset) => self.pc.checked_add_signed(offset).unwrap_or(usize::MAX),
+            };
+        }
+        Some(tick)
+    }
+
+    fn ticks<'a>(&'a mut self, program: &'a Program) -> impl Iterator<Item = Tick> + 'a {
+        std::iter::from_fn(move || self.tick(program))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breakpoint {
+    // Stops before the given cycle runs.
+    Cycle(usize),
+    // Stops before the instruction at the given index starts.
+    Instruction(usize),
+    // Stops after a cycle that changed the register, to the given value if there is one.
+    Watch(Register, Option<i32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopReason {
+    Breakpoint(Breakpoint),
+    Halted,
+}
+
+const CRT_WIDTH: usize = 40;
+const CRT_HEIGHT: usize = 6;
+
+// The screen draws one pixel per cycle, left to right and top to bottom, lighting it when the
+// sprite centred on x covers the pixel's column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    pixels: Vec<bool>,
+}
+
+impl Crt {
+    // A screen needs at least one pixel.
+    fn new(width: usize, height: usize, sprite_width: usize) -> Option<Crt> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(Crt {
+            width,
+            height,
+            sprite_width,
+            pixels: vec![false; width * height],
         })
-        .collect_vec()
+    }
+
+    fn from_ticks(
+        ticks: impl Iterator<Item = Tick>,
+        width: usize,
+        height: usize,
+        sprite_width: usize,
+    ) -> Option<Crt> {
+        let mut crt = Crt::new(width, height, sprite_width)?;
+        for tick in ticks {
+            crt.draw(tick.cycle, tick.x());
+        }
+        Some(crt)
+    }
+
+    // Once the screen is full, drawing starts again from the top left.
+    fn draw(&mut self, cycle: usize, x: i32) {
+        let position = (cycle - 1) % self.pixels.len();
+        let column = (position % self.width) as i32;
+        let sprite_start = x - (self.sprite_width as i32 - 1) / 2;
+        self.pixels[position] =
+            (sprite_start..sprite_start + self.sprite_width as i32).contains(&column);
+    }
+
+    fn to_text(&self) -> String {
+        self.pixels
+            .chunks(self.width)
+            .map(|row| row.iter().map(|&lit| if lit { '#' } else { '.' }).join(""))
+            .join("\n")
+    }
+
+    // Writes the screen as a plain PBM image.
+    fn write_bitmap<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "P1\n{} {}", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width) {
+            writeln!(writer, "{}", row.iter().map(|&lit| lit as u8).join(" "))?;
+        }
+        Ok(())
+    }
+
+    fn letters(&self) -> Result<String, OcrError> {
+        if self.height < GLYPH_HEIGHT {
+            return Err(OcrError::TooShort(self.height));
+        }
+        ocr(&self.pixels, self.width)
+    }
 }
 
-#[allow(dead_code)]
-pub fn day_10() {
-    let op_list = read_input();
+struct Debugger<'a> {
+    program: &'a Program,
+    cpu: Cpu,
+    breakpoints: Vec<Breakpoint>,
+    // The cycle at which `resume` last stopped on a breakpoint, so that resuming moves on.
+    stopped_at: Option<usize>,
+    crt: Crt,
+}
 
-    let register_values_by_cycle = {
-        let cycle_additions = op_list
+impl<'a> Debugger<'a> {
+    fn new(program: &'a Program) -> Debugger<'a> {
+        Debugger {
+            program,
+            cpu: Cpu::new(),
+            breakpoints: Vec::new(),
+            stopped_at: None,
+            crt: Crt::new(CRT_WIDTH, CRT_HEIGHT, 3).unwrap(),
+        }
+    }
+
+    // Runs one cycle, drawing its pixel.
+    fn step(&mut self) -> Option<Tick> {
+        let tick = self.cpu.tick(self.program)?;
+        self.crt.draw(tick.cycle, tick.x());
+        Some(tick)
+    }
+
+    // Runs until the current instruction has completed.
+    fn step_instruction(&mut self) -> Option<Tick> {
+        let mut last_tick = self.step()?;
+        while self.cpu.remaining_cycles.is_some() {
+            last_tick = self.step()?;
+        }
+        Some(last_tick)
+    }
+
+    fn hit_breakpoint(&self, registers_before: [i32; 2]) -> Option<Breakpoint> {
+        self.breakpoints
             .iter()
-            .flat_map(|operation| match operation {
-                Operation::Noop => vec![0],
-                Operation::Addx(x) => vec![0, *x],
+            .copied()
+            .find(|breakpoint| match *breakpoint {
+                Breakpoint::Cycle(cycle) => {
+                    !self.cpu.halted(self.program) && self.cpu.cycle + 1 == cycle
+                }
+                Breakpoint::Instruction(pc) => {
+                    !self.cpu.halted(self.program)
+                        && self.cpu.pc == pc
+                        && self.cpu.remaining_cycles.is_none()
+                }
+                Breakpoint::Watch(register, value) => {
+                    let now = self.cpu.register(register);
+                    now != registers_before[register as usize] && value.is_none_or(|v| v == now)
+                }
             })
-            .collect_vec();
-        let mut cycle_additions_cumsum = cycle_additions
-            .iter()
-            .scan(1, |acc, &x| {
-                *acc += x;
-                Some(*acc)
+    }
+
+    // Runs until a breakpoint is hit or the program ends. Breakpoints on the cycle or instruction
+    // about to run are checked first, unless execution already stopped there.
+    fn resume(&mut self) -> StopReason {
+        if self.stopped_at != Some(self.cpu.cycle) {
+            if let Some(breakpoint) = self.hit_breakpoint(self.cpu.registers) {
+                self.stopped_at = Some(self.cpu.cycle);
+                return StopReason::Breakpoint(breakpoint);
+            }
+        }
+        loop {
+            let registers_before = self.cpu.registers;
+            if self.step().is_none() {
+                return StopReason::Halted;
+            }
+            if let Some(breakpoint) = self.hit_breakpoint(registers_before) {
+                self.stopped_at = Some(self.cpu.cycle);
+                return StopReason::Breakpoint(breakpoint);
+            }
+            if self.cpu.halted(self.program) {
+                return StopReason::Halted;
+            }
+        }
+    }
+
+    fn registers(&self) -> String {
+        let instruction = self
+            .program
+            .get(self.cpu.pc)
+            .map_or("halted".to_string(), |instruction| {
+                format!("{:?}", instruction)
+            });
+        format!(
+            "cycle {} pc {} ({}) x {} y {}",
+            self.cpu.cycle,
+            self.cpu.pc,
+            instruction,
+            self.cpu.register(Register::X),
+            self.cpu.register(Register::Y)
+        )
+    }
+
+    // Runs one debugger command, such as `break cycle 20`, `break pc 3`, `watch x 7`, `step 5`,
+    // `next`, `continue`, `regs` or `crt`, and returns what it prints.
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        let words = line.split_whitespace().collect_vec();
+        let number = |i: usize| -> Result<usize, String> {
+            let word = words.get(i).ok_or("missing argument")?;
+            word.parse().map_err(|_| format!("not a number: {}", word))
+        };
+        match words.as_slice() {
+            ["break", "cycle", ..] => self.breakpoints.push(Breakpoint::Cycle(number(2)?)),
+            ["break", "pc", ..] => self.breakpoints.push(Breakpoint::Instruction(number(2)?)),
+            ["watch", register] | ["watch", register, _] => {
+                let register = match *register {
+                    "x" => Register::X,
+                    "y" => Register::Y,
+                    _ => return Err(format!("no register {}", register)),
+                };
+                let value = match words.get(2) {
+                    Some(word) => Some(
+                        word.parse()
+                            .map_err(|_| format!("not a number: {}", word))?,
+                    ),
+                    None => None,
+                };
+                self.breakpoints.push(Breakpoint::Watch(register, value));
+            }
+            ["delete"] => self.breakpoints.clear(),
+            ["step"] | ["step", _] => {
+                let count = if words.len() > 1 { number(1)? } else { 1 };
+                for _ in 0..count {
+                    if self.step().is_none() {
+                        break;
+                    }
+                }
+                return Ok(self.registers());
+            }
+            ["next"] => {
+                self.step_instruction();
+                return Ok(self.registers());
+            }
+            ["continue"] => {
+                let reason = self.resume();
+                return Ok(format!("{:?}: {}", reason, self.registers()));
+            }
+            ["regs"] => return Ok(self.registers()),
+            ["crt"] => return Ok(self.crt.to_text()),
+            _ => return Err(format!("unknown command: {}", line)),
+        }
+        Ok(String::new())
+    }
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+// The capital letters of the usual puzzle font, each followed by a blank column on screen.
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OcrError {
+    // The screen has fewer rows than a glyph.
+    TooShort(usize),
+    // The position of each glyph that is not in the font, and its pixels.
+    UnknownGlyphs(Vec<(usize, String)>),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::TooShort(height) => write!(
+                f,
+                "a screen {} rows high cannot hold {} row glyphs",
+                height, GLYPH_HEIGHT
+            ),
+            OcrError::UnknownGlyphs(unknown_glyphs) => {
+                write!(f, "unknown glyphs:")?;
+                for (position, glyph) in unknown_glyphs {
+                    write!(f, "\nat {}:\n{}", position, glyph)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Reads the letters on the top rows of a screen `width` pixels wide.
+fn ocr(pixels: &[bool], width: usize) -> Result<String, OcrError> {
+    let glyph_count = (width + 1) / (GLYPH_WIDTH + 1);
+    let mut letters = String::new();
+    let mut unknown_glyphs = Vec::new();
+    for position in 0..glyph_count {
+        let glyph = (0..GLYPH_HEIGHT)
+            .map(|row| {
+                let start = row * width + position * (GLYPH_WIDTH + 1);
+                pixels[start..start + GLYPH_WIDTH]
+                    .iter()
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .collect::<String>()
             })
             .collect_vec();
-        cycle_additions_cumsum.insert(0, 0);
-        cycle_additions_cumsum.insert(1, 1);
-        cycle_additions_cumsum
+        match FONT.iter().find(|(_, rows)| rows[..] == glyph[..]) {
+            Some((letter, _)) => letters.push(*letter),
+            None => unknown_glyphs.push((position, glyph.join("\n"))),
+        }
+    }
+    if unknown_glyphs.is_empty() {
+        Ok(letters)
+    } else {
+        Err(OcrError::UnknownGlyphs(unknown_glyphs))
+    }
+}
+
+fn read_input() -> Program {
+    let file_contents = fs::read_to_string("day10_puzzle.txt").expect("Unable to read file");
+    InstructionSet::standard().parse(&file_contents)
+}
+
+// Raises x to the power of 5 with a countdown loop in y.
+const POWER_EXAMPLE: &str = "addy 5
+mulx 2
+addy -1
+jnz y -2
+jz y 2
+addx 1000
+noop";
+
+#[allow(dead_code)]
+pub fn day_10() {
+    let program = read_input();
+
+    // `debug` reads debugger commands from stdin
+    if std::env::args().nth(1).as_deref() == Some("debug") {
+        let mut debugger = Debugger::new(&program);
+        for line in io::stdin().lines() {
+            match debugger.command(&line.unwrap()) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("error: {}", e),
+            }
+        }
+        return;
+    }
+
+    let crt = Crt::from_ticks(Cpu::new().ticks(&program), CRT_WIDTH, CRT_HEIGHT, 3).unwrap();
+
+    // `bitmap` writes the screen as an image instead
+    if std::env::args().nth(1).as_deref() == Some("bitmap") {
+        crt.write_bitmap(io::stdout().lock()).unwrap();
+        return;
+    }
+
+    let register_values_by_cycle = {
+        let mut values = vec![0];
+        values.extend(Cpu::new().ticks(&program).map(|tick| tick.x()));
+        values
     };
 
     // Star 1
     {
-        let relevant_values = vec![20usize, 60, 100, 140, 180, 220]
+        let relevant_values = [20usize, 60, 100, 140, 180, 220]
             .iter()
             .map(|&i| i as i32 * register_values_by_cycle[i])
             .collect_vec();
@@ -58,17 +626,186 @@ pub fn day_10() {
 
     // Star 2
     {
-        for line in 0..6 {
-            for x in 0..39 {
-                let cycle = line * 40 + x + 1;
-                let draw_sprite = abs(x as i32 - register_values_by_cycle[cycle]) < 2;
-                if draw_sprite {
-                    print!("##");
-                } else {
-                    print!("..");
-                }
-            }
-            println!();
+        println!("{}", crt.to_text());
+        match crt.letters() {
+            Ok(letters) => println!("star 2: {}", letters),
+            Err(e) => println!("{}", e),
         }
     }
+
+    // Extended instructions
+    let power_program = InstructionSet::extended().parse(POWER_EXAMPLE);
+    let mut cpu = Cpu::new();
+    let cycles = cpu.ticks(&power_program).count();
+    println!("2^5 = {} in {} cycles", cpu.register(Register::X), cycles);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register_values_by_cycle(program: &Program) -> Vec<i32> {
+        let mut values = vec![0];
+        values.extend(Cpu::new().ticks(program).map(|tick| tick.x()));
+        values
+    }
+
+    #[test]
+    fn debugger_stops_at_breakpoints() {
+        let program = read_input();
+        let register_values_by_cycle = register_values_by_cycle(&program);
+        let mut debugger = Debugger::new(&program);
+        debugger.command("break cycle 20").unwrap();
+        debugger.command("continue").unwrap();
+        assert_eq!(debugger.cpu.cycle, 19);
+        assert_eq!(
+            debugger.cpu.register(Register::X),
+            register_values_by_cycle[20]
+        );
+        debugger.command("delete").unwrap();
+        debugger.command("step 2").unwrap();
+        assert_eq!(debugger.cpu.cycle, 21);
+        debugger.command("break pc 100").unwrap();
+        debugger.command("continue").unwrap();
+        assert_eq!(debugger.cpu.pc, 100);
+        debugger.command("next").unwrap();
+        assert_eq!(debugger.cpu.pc, 101);
+        debugger.command("delete").unwrap();
+        let watched = register_values_by_cycle[debugger.cpu.cycle + 1..]
+            .iter()
+            .find(|&&x| x != debugger.cpu.register(Register::X))
+            .copied()
+            .unwrap();
+        debugger.command(&format!("watch x {}", watched)).unwrap();
+        debugger.command("continue").unwrap();
+        assert_eq!(debugger.cpu.register(Register::X), watched);
+        assert!(debugger.command("jump 3").is_err());
+        debugger.command("delete").unwrap();
+        debugger.command("continue").unwrap();
+        assert!(debugger.cpu.halted(&program));
+        assert_eq!(
+            debugger.crt,
+            Crt::from_ticks(Cpu::new().ticks(&program), CRT_WIDTH, CRT_HEIGHT, 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn debugger_reports_watchpoint_on_last_instruction() {
+        let program = InstructionSet::standard().parse("noop\naddx 5");
+        let mut debugger = Debugger::new(&program);
+        debugger.command("watch x").unwrap();
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Watch(Register::X, None))
+        );
+        assert_eq!(debugger.cpu.register(Register::X), 6);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+
+        let mut debugger = Debugger::new(&program);
+        debugger.command("break cycle 4").unwrap();
+        assert_eq!(debugger.resume(), StopReason::Halted);
+    }
+
+    #[test]
+    fn extended_instructions_run_loops() {
+        let power_program = InstructionSet::extended().parse(POWER_EXAMPLE);
+        let mut cpu = Cpu::new();
+        let cycles = cpu.ticks(&power_program).count();
+        assert_eq!(cpu.register(Register::X), 32);
+        assert_eq!(cpu.register(Register::Y), 0);
+        assert_eq!(cycles, 2 + 5 * (3 + 2 + 2) + 2 + 1);
+    }
+
+    #[test]
+    fn emulator_matches_puzzle_answer() {
+        let values = register_values_by_cycle(&read_input());
+        let signal_strength = [20, 60, 100, 140, 180, 220]
+            .iter()
+            .map(|&i| i as i32 * values[i])
+            .sum::<i32>();
+        assert_eq!(signal_strength, 15020);
+    }
+
+    fn puzzle_crt() -> Crt {
+        Crt::from_ticks(Cpu::new().ticks(&read_input()), CRT_WIDTH, CRT_HEIGHT, 3).unwrap()
+    }
+
+    #[test]
+    fn puzzle_screen_reads_letters() {
+        assert_eq!(puzzle_crt().letters().unwrap(), "EFUGLPAP");
+    }
+
+    #[test]
+    fn unknown_glyphs_are_reported_by_position() {
+        let mut garbled = puzzle_crt();
+        garbled.pixels[6] = !garbled.pixels[6];
+        let Err(OcrError::UnknownGlyphs(unknown_glyphs)) = garbled.letters() else {
+            panic!("garbled screen should have unknown glyphs");
+        };
+        assert_eq!(unknown_glyphs.iter().map(|(i, _)| *i).collect_vec(), [1]);
+    }
+
+    #[test]
+    fn narrow_sprite_lights_a_subset_of_pixels() {
+        let crt = puzzle_crt();
+        let narrow_sprite =
+            Crt::from_ticks(Cpu::new().ticks(&read_input()), CRT_WIDTH, CRT_HEIGHT, 1).unwrap();
+        assert!(narrow_sprite
+            .pixels
+            .iter()
+            .zip(&crt.pixels)
+            .all(|(&narrow, &wide)| !narrow || wide));
+    }
+
+    #[test]
+    fn screen_shape_is_configurable() {
+        let tall = Crt::from_ticks(Cpu::new().ticks(&read_input()), 20, 12, 3).unwrap();
+        assert!(tall.to_text().split('\n').all(|row| row.len() == 20));
+        assert_eq!(tall.to_text().split('\n').count(), 12);
+    }
+
+    #[test]
+    fn bitmap_has_header_and_one_line_per_row() {
+        let mut bitmap = Vec::new();
+        puzzle_crt().write_bitmap(&mut bitmap).unwrap();
+        assert_eq!(bitmap.split(|&b| b == b'\n').count(), 2 + CRT_HEIGHT + 1);
+    }
+
+    #[test]
+    fn debugger_stops_at_first_cycle_and_instruction() {
+        let program = InstructionSet::standard().parse("noop\naddx 5\nnoop");
+        let mut debugger = Debugger::new(&program);
+        debugger.command("break cycle 1").unwrap();
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Cycle(1))
+        );
+        assert_eq!(debugger.cpu.cycle, 0);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+
+        let mut debugger = Debugger::new(&program);
+        debugger.command("break pc 0").unwrap();
+        debugger.command("break pc 2").unwrap();
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Instruction(0))
+        );
+        assert_eq!(debugger.cpu.cycle, 0);
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Instruction(2))
+        );
+        assert_eq!(debugger.cpu.cycle, 3);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+    }
+
+    #[test]
+    fn odd_screen_shapes_are_rejected() {
+        assert_eq!(Crt::new(0, 6, 3), None);
+        assert_eq!(Crt::new(40, 0, 3), None);
+        assert_eq!(
+            Crt::new(40, 3, 3).unwrap().letters(),
+            Err(OcrError::TooShort(3))
+        );
+    }
 }
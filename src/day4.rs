@@ -1,22 +1,188 @@
+use itertools::Itertools;
+use std::collections::BTreeSet;
 use std::fs;
 
-#[allow(dead_code)]
-pub fn day_4() {
+type Assignment = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct AssignmentId {
+    pair: usize,
+    elf: usize,
+}
+
+fn read_input() -> Vec<(Assignment, Assignment)> {
     let file_contents = fs::read_to_string("day4_puzzle.txt").expect("Unable to read file");
-    let file_lines = file_contents.split("\n").collect::<Vec<&str>>();
-    let elf_pairs = file_lines
+    let file_lines = file_contents.split('\n').collect::<Vec<&str>>();
+    file_lines
         .iter()
         .filter(|line| !line.is_empty())
         .map(|line| {
-            let (sec1, sec2) = line.split_once(",").unwrap();
-            let (sec1l, sec1r) = sec1.split_once("-").unwrap();
-            let (sec2l, sec2r) = sec2.split_once("-").unwrap();
+            let (sec1, sec2) = line.split_once(',').unwrap();
+            let (sec1l, sec1r) = sec1.split_once('-').unwrap();
+            let (sec2l, sec2r) = sec2.split_once('-').unwrap();
             (
                 (sec1l.parse::<i32>().unwrap(), sec1r.parse::<i32>().unwrap()),
                 (sec2l.parse::<i32>().unwrap(), sec2r.parse::<i32>().unwrap()),
             )
         })
-        .collect::<Vec<_>>();
+        .collect::<Vec<_>>()
+}
+
+#[derive(Debug)]
+struct IntervalNode {
+    center: i32,
+    by_start: Vec<(Assignment, AssignmentId)>,
+    by_end: Vec<(Assignment, AssignmentId)>,
+    left: Option<Box<IntervalNode>>,
+    right: Option<Box<IntervalNode>>,
+}
+
+impl IntervalNode {
+    fn build(intervals: Vec<(Assignment, AssignmentId)>) -> Option<Box<IntervalNode>> {
+        if intervals.is_empty() {
+            return None;
+        }
+        let endpoints = intervals
+            .iter()
+            .flat_map(|((l, r), _)| [*l, *r])
+            .sorted()
+            .collect_vec();
+        let center = endpoints[endpoints.len() / 2];
+
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        let mut here = Vec::new();
+        for interval in intervals {
+            let (l, r) = interval.0;
+            if r < center {
+                left.push(interval);
+            } else if l > center {
+                right.push(interval);
+            } else {
+                here.push(interval);
+            }
+        }
+
+        let by_start = here
+            .iter()
+            .copied()
+            .sorted_by_key(|((l, _), _)| *l)
+            .collect_vec();
+        let by_end = here
+            .into_iter()
+            .sorted_by_key(|((_, r), _)| -r)
+            .collect_vec();
+        Some(Box::new(IntervalNode {
+            center,
+            by_start,
+            by_end,
+            left: IntervalNode::build(left),
+            right: IntervalNode::build(right),
+        }))
+    }
+
+    fn query(&self, (l, r): Assignment, result: &mut Vec<AssignmentId>) {
+        if r < self.center {
+            result.extend(
+                self.by_start
+                    .iter()
+                    .take_while(|((start, _), _)| *start <= r)
+                    .map(|(_, id)| *id),
+            );
+            if let Some(left) = &self.left {
+                left.query((l, r), result);
+            }
+        } else if l > self.center {
+            result.extend(
+                self.by_end
+                    .iter()
+                    .take_while(|((_, end), _)| *end >= l)
+                    .map(|(_, id)| *id),
+            );
+            if let Some(right) = &self.right {
+                right.query((l, r), result);
+            }
+        } else {
+            result.extend(self.by_start.iter().map(|(_, id)| *id));
+            if let Some(left) = &self.left {
+                left.query((l, r), result);
+            }
+            if let Some(right) = &self.right {
+                right.query((l, r), result);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SectionIndex {
+    assignments: Vec<(Assignment, AssignmentId)>,
+    root: Option<Box<IntervalNode>>,
+}
+
+impl SectionIndex {
+    fn from_elf_pairs(elf_pairs: &[(Assignment, Assignment)]) -> SectionIndex {
+        let assignments = elf_pairs
+            .iter()
+            .enumerate()
+            .flat_map(|(pair, (a, b))| {
+                [
+                    (*a, AssignmentId { pair, elf: 0 }),
+                    (*b, AssignmentId { pair, elf: 1 }),
+                ]
+            })
+            .collect_vec();
+        SectionIndex {
+            root: IntervalNode::build(assignments.clone()),
+            assignments,
+        }
+    }
+
+    fn get(&self, id: AssignmentId) -> Assignment {
+        self.assignments[2 * id.pair + id.elf].0
+    }
+
+    fn overlapping(&self, range: Assignment) -> Vec<AssignmentId> {
+        let mut result = Vec::new();
+        if let Some(root) = &self.root {
+            root.query(range, &mut result);
+        }
+        result.sort();
+        result
+    }
+
+    fn containing(&self, section: i32) -> Vec<AssignmentId> {
+        self.overlapping((section, section))
+    }
+
+    fn overlapping_pairs(&self) -> Vec<(AssignmentId, AssignmentId)> {
+        let mut result = Vec::new();
+        let mut active = BTreeSet::new();
+        for ((l, r), id) in self
+            .assignments
+            .iter()
+            .sorted_by_key(|((l, _), id)| (*l, *id))
+        {
+            while let Some(&(end, _)) = active.first() {
+                if end >= *l {
+                    break;
+                }
+                active.pop_first();
+            }
+            result.extend(active.iter().map(|(_, other)| (*other, *id)));
+            active.insert((*r, *id));
+        }
+        result
+            .into_iter()
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .sorted()
+            .collect_vec()
+    }
+}
+
+#[allow(dead_code)]
+pub fn day_4() {
+    let elf_pairs = read_input();
     let pair_overlaps = elf_pairs
         .iter()
         .map(|((sec1l, sec1r), (sec2l, sec2r))| {
@@ -31,4 +197,91 @@ pub fn day_4() {
     println!("{:?}", pair_overlaps);
     println!("{:?}", pair_overlaps.iter().filter(|(all, _)| *all).count());
     println!("{:?}", pair_overlaps.iter().filter(|(_, any)| *any).count());
+
+    // Index queries
+    let index = SectionIndex::from_elf_pairs(&elf_pairs);
+    for section in [1, 57, 99] {
+        println!(
+            "assignments containing {}: {}",
+            section,
+            index.containing(section).len()
+        );
+    }
+    let overlapping_pairs = index.overlapping_pairs();
+    println!(
+        "overlapping assignments in file: {}, first: {:?}",
+        overlapping_pairs.len(),
+        overlapping_pairs
+            .first()
+            .map(|(a, b)| (index.get(*a), index.get(*b)))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &Assignment, b: &Assignment) -> bool {
+        a.0 <= b.1 && b.0 <= a.1
+    }
+
+    const EXAMPLE: [(Assignment, Assignment); 6] = [
+        ((2, 4), (6, 8)),
+        ((2, 3), (4, 5)),
+        ((5, 7), (7, 9)),
+        ((2, 8), (3, 7)),
+        ((6, 6), (4, 6)),
+        ((2, 6), (4, 8)),
+    ];
+
+    fn check_against_brute_force(elf_pairs: &[(Assignment, Assignment)]) {
+        let index = SectionIndex::from_elf_pairs(elf_pairs);
+        let max_section = index
+            .assignments
+            .iter()
+            .map(|((_, r), _)| *r)
+            .max()
+            .unwrap();
+        for section in 0..=max_section + 1 {
+            let brute_force = index
+                .assignments
+                .iter()
+                .filter(|((l, r), _)| *l <= section && section <= *r)
+                .map(|(_, id)| *id)
+                .collect_vec();
+            assert_eq!(index.containing(section), brute_force);
+        }
+        for range in [(0, 0), (3, 5), (10, 20), (50, 60), (1, 99)] {
+            let brute_force = index
+                .assignments
+                .iter()
+                .filter(|(assignment, _)| overlaps(assignment, &range))
+                .map(|(_, id)| *id)
+                .collect_vec();
+            assert_eq!(index.overlapping(range), brute_force);
+        }
+
+        let overlapping_pairs = index.overlapping_pairs();
+        let brute_force = index
+            .assignments
+            .iter()
+            .tuple_combinations()
+            .filter(|((a, _), (b, _))| overlaps(a, b))
+            .map(|((_, a), (_, b))| (*a, *b))
+            .collect_vec();
+        assert_eq!(overlapping_pairs, brute_force);
+        assert!(overlapping_pairs
+            .iter()
+            .all(|(a, b)| overlaps(&index.get(*a), &index.get(*b))));
+    }
+
+    #[test]
+    fn example_matches_brute_force() {
+        check_against_brute_force(&EXAMPLE);
+    }
+
+    #[test]
+    fn puzzle_matches_brute_force() {
+        check_against_brute_force(&read_input());
+    }
 }
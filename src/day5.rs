@@ -1,6 +1,10 @@
 use itertools::Itertools;
 use regex::Regex;
-use std::fs;
+use std::cmp::{max, min, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::num::NonZeroUsize;
+use std::{env, fs};
 
 #[derive(Debug)]
 struct Day5Input {
@@ -9,97 +13,974 @@ struct Day5Input {
 }
 
 impl Day5Input {
-    fn from_input(file_contents: &str) -> Day5Input {
-        let (file_contents_1, file_contents_2) = file_contents.split_once("\n\n").unwrap();
-
-        let initial_stacks = {
-            let mut lines = file_contents_1.split("\n").collect::<Vec<&str>>();
-            let last_line = *lines.last().unwrap();
-            lines.truncate(lines.len() - 1);
-            let number_of_stacks = last_line.split_ascii_whitespace().count();
-            let mut result = vec![Vec::new(); number_of_stacks];
-            for i in 0..result.len() {
-                let mut stack = Vec::new();
-                for l in lines.iter() {
-                    let c = l.chars().nth(1 + 4 * i).unwrap().clone();
-                    if c.is_alphabetic() {
-                        stack.push(c);
-                    }
-                }
-                result[i] = stack.into_iter().rev().collect::<Vec<_>>();
-            }
-            result
+    fn from_input(file_contents: &str) -> Result<Day5Input, ParseError> {
+        let Some((file_contents_1, file_contents_2)) = file_contents.split_once("\n\n") else {
+            return Err(ParseError {
+                line: file_contents.split('\n').count(),
+                text: String::new(),
+                kind: ParseErrorKind::MissingMoveList,
+            });
         };
 
+        let initial_stacks = Stacks::from_drawing(file_contents_1)?;
+
         let move_list = {
             let regex = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
+            let first_move_line = file_contents_1.split('\n').count() + 2;
             file_contents_2
-                .split("\n")
-                .filter(|line| !line.is_empty())
-                .map(|line| {
-                    let captures = regex.captures(line).unwrap();
-                    assert_eq!(captures.len(), 4);
-                    let (c1, c2, c3) = (&captures[1], &captures[2], &captures[3]);
-                    let crates = c1.parse::<usize>().unwrap();
-                    let from = c2.parse::<usize>().unwrap();
-                    let to = c3.parse::<usize>().unwrap();
-                    StackMove { from, to, crates }
+                .split('\n')
+                .enumerate()
+                .filter(|(_, line)| !line.is_empty())
+                .map(|(i, line)| {
+                    let line_number = first_move_line + i;
+                    let numbers = regex.captures(line).and_then(|captures| {
+                        let number = |i: usize| captures[i].parse::<usize>().ok();
+                        Some((number(1)?, number(2)?, number(3)?))
+                    });
+                    let Some((crates, from, to)) = numbers else {
+                        return Err(ParseError {
+                            line: line_number,
+                            text: line.to_string(),
+                            kind: ParseErrorKind::NotAMove,
+                        });
+                    };
+                    Ok(StackMove {
+                        from,
+                        to,
+                        crates,
+                        line: Some(line_number),
+                    })
                 })
-                .collect::<Vec<_>>()
+                .collect::<Result<Vec<_>, _>>()?
         };
 
-        Day5Input {
-            initial_stacks: Stacks(initial_stacks),
+        Ok(Day5Input {
+            initial_stacks,
             move_list,
-        }
+        })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Stacks(Vec<Vec<char>>);
 
 impl Stacks {
-    fn apply_move_9000(&mut self, stack_move: &StackMove) {
+    fn from_drawing(drawing: &str) -> Result<Stacks, ParseError> {
+        let mut lines = drawing
+            .split('\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .collect_vec();
+        let Some((footer_index, footer)) = lines.pop() else {
+            return Err(ParseError {
+                line: 1,
+                text: String::new(),
+                kind: ParseErrorKind::EmptyDrawing,
+            });
+        };
+        let label_centers = footer
+            .char_indices()
+            .group_by(|(_, c)| c.is_ascii_digit())
+            .into_iter()
+            .filter(|(is_digit, _)| *is_digit)
+            .map(|(_, digits)| {
+                let positions = digits.map(|(i, _)| 2 * i).collect_vec();
+                (positions.first().unwrap() + positions.last().unwrap()) / 2
+            })
+            .collect_vec();
+        if label_centers.is_empty() {
+            return Err(ParseError {
+                line: footer_index + 1,
+                text: footer.to_string(),
+                kind: ParseErrorKind::NoStackLabels,
+            });
+        }
+
+        let mut stacks = vec![Vec::new(); label_centers.len()];
+        for (_, line) in lines.iter().rev() {
+            let chars = line.chars().collect_vec();
+            for (i, window) in chars.windows(3).enumerate() {
+                if window[0] == '[' && window[2] == ']' {
+                    let (stack, _) = label_centers
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, center)| center.abs_diff(2 * (i + 1)))
+                        .unwrap();
+                    stacks[stack].push(window[1]);
+                }
+            }
+        }
+        Ok(Stacks(stacks))
+    }
+
+    fn validate_move(&self, stack_move: &StackMove) -> Result<(), MoveError> {
+        let error = |kind| {
+            Err(MoveError {
+                stack_move: stack_move.clone(),
+                kind,
+            })
+        };
+        for stack in [stack_move.from, stack_move.to] {
+            if stack == 0 || stack > self.0.len() {
+                return error(MoveErrorKind::NoSuchStack(stack));
+            }
+        }
+        if stack_move.from == stack_move.to {
+            return error(MoveErrorKind::SameStack);
+        }
+        let available = self.0[stack_move.from - 1].len();
+        if available < stack_move.crates {
+            return error(MoveErrorKind::NotEnoughCrates { available });
+        }
+        Ok(())
+    }
+
+    // Lower bound on the number of moves needed to reach `target`: every stack that is not a
+    // prefix of its target stack must be moved from, every stack missing crates must be moved to.
+    fn moves_lower_bound(&self, target: &Stacks) -> usize {
+        let (mut to_empty, mut to_fill) = (0, 0);
+        for (stack, target_stack) in self.0.iter().zip(target.0.iter()) {
+            if !target_stack.starts_with(stack) {
+                to_empty += 1;
+            } else if stack.len() < target_stack.len() {
+                to_fill += 1;
+            }
+        }
+        max(to_empty, to_fill)
+    }
+
+    fn top_elements(&self) -> String {
+        self.0.iter().filter_map(|stack| stack.last()).join("")
+    }
+}
+
+impl Display for Stacks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = max(3, self.0.len().to_string().len());
+        let height = self.0.iter().map(|stack| stack.len()).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            let line = self
+                .0
+                .iter()
+                .map(|stack| match stack.get(level) {
+                    Some(c) => format!("{:^width$}", format!("[{}]", c)),
+                    None => " ".repeat(width),
+                })
+                .join(" ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        let footer = (1..=self.0.len())
+            .map(|label| format!("{:^width$}", label))
+            .join(" ");
+        write!(f, "{}", footer.trim_end())
+    }
+}
+
+trait Crane {
+    fn name(&self) -> String;
+
+    // Applies the move and returns its cost.
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize;
+
+    // Whether a move has the same effect no matter which moves came before.
+    fn is_stateless(&self) -> bool {
+        true
+    }
+}
+
+struct Crane9000;
+
+impl Crane for Crane9000 {
+    fn name(&self) -> String {
+        "CrateMover 9000".to_string()
+    }
+
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize {
         for _ in 0..stack_move.crates {
-            let x = self.0[stack_move.from - 1].pop().unwrap();
-            self.0[stack_move.to - 1].push(x);
+            let x = stacks.0[stack_move.from - 1].pop().unwrap();
+            stacks.0[stack_move.to - 1].push(x);
         }
+        stack_move.crates
+    }
+}
+
+struct Crane9001;
+
+impl Crane for Crane9001 {
+    fn name(&self) -> String {
+        "CrateMover 9001".to_string()
     }
 
-    fn apply_move_9001(&mut self, stack_move: &StackMove) {
-        let from_stack = &mut self.0[stack_move.from - 1];
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize {
+        let from_stack = &mut stacks.0[stack_move.from - 1];
         let mut moved_elements = from_stack
             .drain(from_stack.len() - stack_move.crates..)
             .collect_vec();
-        self.0[stack_move.to - 1].append(&mut moved_elements);
+        stacks.0[stack_move.to - 1].append(&mut moved_elements);
+        1
     }
 }
 
-#[derive(Debug)]
+// Lifts at most `capacity` crates at once, splitting larger moves into chunks.
+struct LimitedCapacityCrane {
+    capacity: NonZeroUsize,
+}
+
+impl Crane for LimitedCapacityCrane {
+    fn name(&self) -> String {
+        format!("limited capacity crane ({})", self.capacity)
+    }
+
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize {
+        let mut remaining = stack_move.crates;
+        let mut lifts = 0;
+        while remaining > 0 {
+            let crates = min(remaining, self.capacity.get());
+            Crane9001.apply_move(
+                stacks,
+                &StackMove {
+                    from: stack_move.from,
+                    to: stack_move.to,
+                    crates,
+                    line: stack_move.line,
+                },
+            );
+            remaining -= crates;
+            lifts += 1;
+        }
+        lifts
+    }
+}
+
+// Lifts all crates at once, but flips the lifted block on every other move.
+struct AlternatingCrane {
+    reverse_next: bool,
+}
+
+impl Crane for AlternatingCrane {
+    fn name(&self) -> String {
+        "alternating crane".to_string()
+    }
+
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize {
+        if self.reverse_next {
+            Crane9000.apply_move(stacks, stack_move);
+        } else {
+            Crane9001.apply_move(stacks, stack_move);
+        }
+        self.reverse_next = !self.reverse_next;
+        1
+    }
+
+    fn is_stateless(&self) -> bool {
+        false
+    }
+}
+
+// Wraps another crane and charges a fixed cost per move, per crate and per stack travelled.
+struct CostAccountingCrane {
+    inner: Box<dyn Crane>,
+    cost_per_move: usize,
+    cost_per_crate: usize,
+    cost_per_distance: usize,
+}
+
+impl Crane for CostAccountingCrane {
+    fn name(&self) -> String {
+        format!("{} with cost accounting", self.inner.name())
+    }
+
+    fn is_stateless(&self) -> bool {
+        self.inner.is_stateless()
+    }
+
+    fn apply_move(&mut self, stacks: &mut Stacks, stack_move: &StackMove) -> usize {
+        self.inner.apply_move(stacks, stack_move);
+        self.cost_per_move
+            + self.cost_per_crate * stack_move.crates
+            + self.cost_per_distance * stack_move.from.abs_diff(stack_move.to)
+    }
+}
+
+fn crane_from_name(name: &str) -> Option<Box<dyn Crane>> {
+    let (kind, argument) = name.split_once(':').unwrap_or((name, ""));
+    let crane: Box<dyn Crane> = match kind {
+        "9000" => Box::new(Crane9000),
+        "9001" => Box::new(Crane9001),
+        "limited" => Box::new(LimitedCapacityCrane {
+            capacity: argument.parse().ok()?,
+        }),
+        "alternating" => Box::new(AlternatingCrane { reverse_next: true }),
+        "cost" => Box::new(CostAccountingCrane {
+            inner: crane_from_name(if argument.is_empty() {
+                "9001"
+            } else {
+                argument
+            })?,
+            cost_per_move: 10,
+            cost_per_crate: 1,
+            cost_per_distance: 2,
+        }),
+        _ => return None,
+    };
+    Some(crane)
+}
+
+// A* search for a shortest move list turning `initial` into `target` with the given crane. Cranes
+// whose moves depend on earlier moves cannot be searched this way and give `None`.
+fn find_shortest_moves(
+    crane: &mut dyn Crane,
+    initial: &Stacks,
+    target: &Stacks,
+) -> Option<Vec<StackMove>> {
+    if !crane.is_stateless() {
+        return None;
+    }
+    let crates = |stacks: &Stacks| stacks.0.iter().flatten().copied().sorted().collect_vec();
+    if initial.0.len() != target.0.len() || crates(initial) != crates(target) {
+        return None;
+    }
+
+    let mut visited = HashSet::new();
+    let mut best: HashMap<Stacks, (usize, Option<(Stacks, StackMove)>)> =
+        HashMap::from([(initial.clone(), (0, None))]);
+    let mut queue = BinaryHeap::from([Reverse((
+        initial.moves_lower_bound(target),
+        0,
+        initial.clone(),
+    ))]);
+    while let Some(Reverse((_, distance, stacks))) = queue.pop() {
+        if stacks == *target {
+            break;
+        }
+        if !visited.insert(stacks.clone()) {
+            continue;
+        }
+        for (from, to) in (1..=stacks.0.len()).tuple_combinations() {
+            for (from, to) in [(from, to), (to, from)] {
+                for crates in 1..=stacks.0[from - 1].len() {
+                    let stack_move = StackMove {
+                        from,
+                        to,
+                        crates,
+                        line: None,
+                    };
+                    let mut next = stacks.clone();
+                    crane.apply_move(&mut next, &stack_move);
+                    let known_distance = best.get(&next).map_or(usize::MAX, |(d, _)| *d);
+                    if distance + 1 < known_distance {
+                        best.insert(
+                            next.clone(),
+                            (distance + 1, Some((stacks.clone(), stack_move))),
+                        );
+                        queue.push(Reverse((
+                            distance + 1 + next.moves_lower_bound(target),
+                            distance + 1,
+                            next,
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut move_list = Vec::new();
+    let mut current = target.clone();
+    while let Some((previous, stack_move)) = best.get(&current)?.1.clone() {
+        move_list.push(stack_move);
+        current = previous;
+    }
+    move_list.reverse();
+    Some(move_list)
+}
+
+fn is_optimal(crane: &mut dyn Crane, initial: &Stacks, move_list: &[StackMove]) -> bool {
+    let Ok((target, _)) = run_crane(crane, initial, move_list) else {
+        return false;
+    };
+    find_shortest_moves(crane, initial, &target).map(|moves| moves.len()) == Some(move_list.len())
+}
+
+fn run_crane(
+    crane: &mut dyn Crane,
+    stacks: &Stacks,
+    move_list: &[StackMove],
+) -> Result<(Stacks, usize), MoveError> {
+    let mut simulation = Simulation::new(crane, stacks, move_list);
+    simulation.run()?;
+    Ok((simulation.stacks.clone(), simulation.total_cost()))
+}
+
+#[derive(Debug, Clone)]
 struct StackMove {
     from: usize,
     to: usize,
     crates: usize,
+    // The input line the move was read from, if any.
+    line: Option<usize>,
+}
+
+impl Display for StackMove {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "move {} from {} to {}", self.crates, self.from, self.to)
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MoveErrorKind {
+    NoSuchStack(usize),
+    NotEnoughCrates { available: usize },
+    SameStack,
+}
+
+#[derive(Debug, Clone)]
+struct MoveError {
+    stack_move: StackMove,
+    kind: MoveErrorKind,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.stack_move.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "\"{}\": ", self.stack_move)?;
+        match self.kind {
+            MoveErrorKind::NoSuchStack(stack) => write!(f, "there is no stack {}", stack),
+            MoveErrorKind::NotEnoughCrates { available } => write!(
+                f,
+                "stack {} only holds {} crates",
+                self.stack_move.from, available
+            ),
+            MoveErrorKind::SameStack => write!(f, "source and target stack are the same"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseErrorKind {
+    EmptyDrawing,
+    NoStackLabels,
+    MissingMoveList,
+    NotAMove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParseError {
+    line: usize,
+    text: String,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: \"{}\": ", self.line, self.text)?;
+        match self.kind {
+            ParseErrorKind::EmptyDrawing => write!(f, "the drawing has no stacks"),
+            ParseErrorKind::NoStackLabels => {
+                write!(f, "the last line of the drawing should number the stacks")
+            }
+            ParseErrorKind::MissingMoveList => {
+                write!(f, "expected a blank line between the drawing and the moves")
+            }
+            ParseErrorKind::NotAMove => write!(f, "expected \"move <n> from <a> to <b>\""),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AppliedMove {
+    stack_move: StackMove,
+    lifted: Vec<char>,
+    placed: Vec<char>,
+    cost: usize,
+}
+
+// Steps a crane through a move list, remembering what every move did so it can be undone and redone.
+struct Simulation<'a> {
+    stacks: Stacks,
+    crane: &'a mut dyn Crane,
+    move_list: &'a [StackMove],
+    history: Vec<AppliedMove>,
+    position: usize,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(
+        crane: &'a mut dyn Crane,
+        stacks: &Stacks,
+        move_list: &'a [StackMove],
+    ) -> Simulation<'a> {
+        Simulation {
+            stacks: stacks.clone(),
+            crane,
+            move_list,
+            history: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn total_cost(&self) -> usize {
+        self.history[..self.position]
+            .iter()
+            .map(|applied| applied.cost)
+            .sum()
+    }
+
+    fn step_forward(&mut self) -> Result<bool, MoveError> {
+        if let Some(applied) = self.history.get(self.position) {
+            let stack_move = &applied.stack_move;
+            let from_stack = &mut self.stacks.0[stack_move.from - 1];
+            from_stack.truncate(from_stack.len() - stack_move.crates);
+            self.stacks.0[stack_move.to - 1].extend(&applied.placed);
+        } else if let Some(stack_move) = self.move_list.get(self.position) {
+            self.stacks.validate_move(stack_move)?;
+            let from_stack = &self.stacks.0[stack_move.from - 1];
+            let lifted = from_stack[from_stack.len() - stack_move.crates..].to_vec();
+            let cost = self.crane.apply_move(&mut self.stacks, stack_move);
+            let to_stack = &self.stacks.0[stack_move.to - 1];
+            let placed = to_stack[to_stack.len() - stack_move.crates..].to_vec();
+            self.history.push(AppliedMove {
+                stack_move: stack_move.clone(),
+                lifted,
+                placed,
+                cost,
+            });
+        } else {
+            return Ok(false);
+        }
+        self.position += 1;
+        Ok(true)
+    }
+
+    fn step_back(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        let applied = &self.history[self.position];
+        let stack_move = &applied.stack_move;
+        let to_stack = &mut self.stacks.0[stack_move.to - 1];
+        to_stack.truncate(to_stack.len() - stack_move.crates);
+        self.stacks.0[stack_move.from - 1].extend(&applied.lifted);
+        true
+    }
+
+    fn seek(&mut self, position: usize) -> Result<(), MoveError> {
+        while self.position > position {
+            self.step_back();
+        }
+        while self.position < position && self.step_forward()? {}
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), MoveError> {
+        while self.step_forward()? {}
+        Ok(())
+    }
+}
+
+const EXAMPLE: &str = "    [D]
+[N] [C]
+[Z] [M] [P]
+ 1   2   3
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2
+";
+
 #[allow(dead_code)]
 pub fn day_5() {
     let file_contents = fs::read_to_string("day5_puzzle.txt").expect("Unable to read file");
-    let input = Day5Input::from_input(&file_contents);
+    let input = Day5Input::from_input(&file_contents).unwrap_or_else(|error| panic!("{}", error));
 
     // Star 1
-    let mut stacks = input.initial_stacks.clone();
-    for stack_move in input.move_list.iter() {
-        stacks.apply_move_9000(stack_move);
-    }
-    let top_elements = stacks.0.iter().map(|stack| stack.last().unwrap()).join("");
-    println!("{:?}", top_elements);
+    let (stacks, _) = run_crane(&mut Crane9000, &input.initial_stacks, &input.move_list)
+        .unwrap_or_else(|error| panic!("{}", error));
+    println!("{:?}", stacks.top_elements());
 
     // Star 2
-    let mut stacks = input.initial_stacks.clone();
-    for stack_move in input.move_list.iter() {
-        stacks.apply_move_9001(stack_move);
+    let (stacks, _) = run_crane(&mut Crane9001, &input.initial_stacks, &input.move_list)
+        .unwrap_or_else(|error| panic!("{}", error));
+    println!("{:?}", stacks.top_elements());
+
+    println!("{}", input.initial_stacks);
+
+    // Stepping through the moves of a stateful crane
+    {
+        let mut crane = AlternatingCrane { reverse_next: true };
+        let mut simulation = Simulation::new(&mut crane, &input.initial_stacks, &input.move_list);
+        simulation.run().unwrap();
+        let halfway = input.move_list.len() / 2;
+        simulation.seek(halfway).unwrap();
+        println!(
+            "{} after {} moves: {:?}, cost so far: {}",
+            simulation.crane.name(),
+            halfway,
+            simulation.stacks.top_elements(),
+            simulation.total_cost()
+        );
+    }
+
+    // Shortest move lists for the example
+    {
+        let example = Day5Input::from_input(EXAMPLE).unwrap();
+        let cranes: [&mut dyn Crane; 3] = [
+            &mut Crane9000,
+            &mut Crane9001,
+            &mut LimitedCapacityCrane {
+                capacity: NonZeroUsize::new(2).unwrap(),
+            },
+        ];
+        for crane in cranes {
+            let (target, _) =
+                run_crane(crane, &example.initial_stacks, &example.move_list).unwrap();
+            let shortest = find_shortest_moves(crane, &example.initial_stacks, &target).unwrap();
+            println!(
+                "{}: {} moves ({}), example is optimal: {}",
+                crane.name(),
+                shortest.len(),
+                shortest
+                    .iter()
+                    .map(|stack_move| stack_move.to_string())
+                    .join(", "),
+                is_optimal(crane, &example.initial_stacks, &example.move_list)
+            );
+        }
+    }
+
+    // Crane selected on the command line, e.g. `limited:3` or `cost:9000`
+    if let Some(name) = env::args().nth(1) {
+        let mut crane = crane_from_name(&name).expect("Unknown crane");
+        match run_crane(crane.as_mut(), &input.initial_stacks, &input.move_list) {
+            Ok((stacks, total_cost)) => println!(
+                "{}: {:?}, total cost: {}",
+                crane.name(),
+                stacks.top_elements(),
+                total_cost
+            ),
+            Err(error) => println!("{}: {}", crane.name(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle_input() -> (String, Day5Input) {
+        let file_contents = fs::read_to_string("day5_puzzle.txt").expect("Unable to read file");
+        let input =
+            Day5Input::from_input(&file_contents).unwrap_or_else(|error| panic!("{}", error));
+        (file_contents, input)
+    }
+
+    #[test]
+    fn drawing_round_trips() {
+        for file_contents in [EXAMPLE.to_string(), puzzle_input().0] {
+            let input =
+                Day5Input::from_input(&file_contents).unwrap_or_else(|error| panic!("{}", error));
+            let drawing = file_contents.split_once("\n\n").unwrap().0;
+            assert_eq!(input.initial_stacks.to_string(), drawing);
+            assert_eq!(Stacks::from_drawing(drawing).unwrap(), input.initial_stacks);
+        }
+    }
+
+    #[test]
+    fn wide_drawing_round_trips() {
+        let mut wide_stacks = puzzle_input().1.initial_stacks;
+        wide_stacks
+            .0
+            .extend([vec![], vec!['A'], vec!['B', 'C', 'D']]);
+        assert!(wide_stacks.to_string().ends_with(" 12"));
+        assert_eq!(
+            Stacks::from_drawing(&wide_stacks.to_string()).unwrap(),
+            wide_stacks
+        );
+    }
+
+    #[test]
+    fn drawing_with_trailing_whitespace_parses() {
+        let stacks = Day5Input::from_input(EXAMPLE).unwrap().initial_stacks;
+        let padded = stacks
+            .to_string()
+            .split('\n')
+            .map(|line| format!("{}   ", line))
+            .join("\n");
+        assert_eq!(Stacks::from_drawing(&padded).unwrap(), stacks);
+        let trimmed = stacks
+            .to_string()
+            .split('\n')
+            .map(|line| line.trim_end())
+            .join("\n");
+        assert_eq!(Stacks::from_drawing(&trimmed).unwrap(), stacks);
+    }
+
+    #[test]
+    fn stepping_back_and_replaying_is_consistent() {
+        let input = puzzle_input().1;
+        let mut crane = AlternatingCrane { reverse_next: true };
+        let mut simulation = Simulation::new(&mut crane, &input.initial_stacks, &input.move_list);
+        simulation.run().unwrap();
+        let final_stacks = simulation.stacks.clone();
+        simulation.seek(input.move_list.len() / 2).unwrap();
+        simulation.seek(0).unwrap();
+        assert_eq!(simulation.stacks, input.initial_stacks);
+        simulation.run().unwrap();
+        assert_eq!(simulation.stacks, final_stacks);
+    }
+
+    fn run_example(crane: &mut dyn Crane) -> (String, usize) {
+        let example = Day5Input::from_input(EXAMPLE).unwrap();
+        let (stacks, total_cost) =
+            run_crane(crane, &example.initial_stacks, &example.move_list).unwrap();
+        (stacks.to_string(), total_cost)
+    }
+
+    #[test]
+    fn cranes_rearrange_example_and_report_cost() {
+        assert_eq!(
+            run_example(&mut Crane9000),
+            (
+                "        [Z]\n        [N]\n        [D]\n[C] [M] [P]\n 1   2   3".to_string(),
+                7
+            )
+        );
+        assert_eq!(
+            run_example(&mut Crane9001),
+            (
+                "        [D]\n        [N]\n        [Z]\n[M] [C] [P]\n 1   2   3".to_string(),
+                4
+            )
+        );
+        let mut limited = LimitedCapacityCrane {
+            capacity: NonZeroUsize::new(2).unwrap(),
+        };
+        assert_eq!(
+            run_example(&mut limited),
+            (
+                "        [Z]\n        [D]\n        [N]\n[M] [C] [P]\n 1   2   3".to_string(),
+                5
+            )
+        );
+        let mut alternating = AlternatingCrane { reverse_next: true };
+        assert_eq!(
+            run_example(&mut alternating),
+            (
+                "        [D]\n        [N]\n        [Z]\n[C] [M] [P]\n 1   2   3".to_string(),
+                4
+            )
+        );
+        let mut cost_accounting = CostAccountingCrane {
+            inner: Box::new(Crane9001),
+            cost_per_move: 10,
+            cost_per_crate: 1,
+            cost_per_distance: 2,
+        };
+        assert_eq!(
+            run_example(&mut cost_accounting),
+            (
+                "        [D]\n        [N]\n        [Z]\n[M] [C] [P]\n 1   2   3".to_string(),
+                57
+            )
+        );
+    }
+
+    #[test]
+    fn cranes_are_selected_by_name() {
+        let name = |name: &str| crane_from_name(name).map(|crane| crane.name());
+        assert_eq!(name("9000").as_deref(), Some("CrateMover 9000"));
+        assert_eq!(name("9001").as_deref(), Some("CrateMover 9001"));
+        assert_eq!(
+            name("limited:3").as_deref(),
+            Some("limited capacity crane (3)")
+        );
+        assert_eq!(name("alternating").as_deref(), Some("alternating crane"));
+        assert_eq!(
+            name("cost:9000").as_deref(),
+            Some("CrateMover 9000 with cost accounting")
+        );
+        assert_eq!(
+            name("cost").as_deref(),
+            Some("CrateMover 9001 with cost accounting")
+        );
+        for invalid in ["limited", "limited:0", "limited:x", "cost:9002", "9002"] {
+            assert_eq!(name(invalid), None, "{}", invalid);
+        }
+        let (stacks, total_cost) = run_example(crane_from_name("cost:9000").unwrap().as_mut());
+        assert_eq!(stacks, run_example(&mut Crane9000).0);
+        assert_eq!(total_cost, 4 * 10 + 7 + 2 * (1 + 2 + 1 + 1));
+    }
+
+    #[test]
+    fn malformed_move_lines_are_reported() {
+        let input = EXAMPLE.replace("move 2 from 2 to 1", "move two from 2 to 1");
+        let error = Day5Input::from_input(&input).unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                line: 8,
+                text: "move two from 2 to 1".to_string(),
+                kind: ParseErrorKind::NotAMove,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 8: \"move two from 2 to 1\": expected \"move <n> from <a> to <b>\""
+        );
+        let input = EXAMPLE.replace(
+            "move 1 from 2 to 1",
+            "move 99999999999999999999 from 2 to 1",
+        );
+        assert_eq!(Day5Input::from_input(&input).unwrap_err().line, 6);
+    }
+
+    #[test]
+    fn malformed_drawings_are_reported() {
+        assert_eq!(
+            Stacks::from_drawing("").unwrap_err().kind,
+            ParseErrorKind::EmptyDrawing
+        );
+        assert_eq!(
+            Stacks::from_drawing("   \n").unwrap_err().kind,
+            ParseErrorKind::EmptyDrawing
+        );
+        let error = Stacks::from_drawing("[A]\n[B] [C]").unwrap_err();
+        assert_eq!((error.line, error.kind), (2, ParseErrorKind::NoStackLabels));
+        assert_eq!(
+            Day5Input::from_input(" 1 \nmove 1 from 1 to 1")
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::MissingMoveList
+        );
+        assert_eq!(
+            Stacks::from_drawing(" 1   2"),
+            Ok(Stacks(vec![vec![], vec![]]))
+        );
+    }
+
+    // Breadth-first distances from `initial` to every arrangement the crane can reach.
+    fn brute_force_distances(crane: &mut dyn Crane, initial: &Stacks) -> HashMap<Stacks, usize> {
+        let mut distances = HashMap::from([(initial.clone(), 0)]);
+        let mut frontier = vec![initial.clone()];
+        while !frontier.is_empty() {
+            let mut next_frontier = Vec::new();
+            for stacks in frontier {
+                for (from, to) in (1..=stacks.0.len()).tuple_combinations() {
+                    for (from, to) in [(from, to), (to, from)] {
+                        for crates in 1..=stacks.0[from - 1].len() {
+                            let mut next = stacks.clone();
+                            let stack_move = StackMove {
+                                from,
+                                to,
+                                crates,
+                                line: None,
+                            };
+                            crane.apply_move(&mut next, &stack_move);
+                            if !distances.contains_key(&next) {
+                                distances.insert(next.clone(), distances[&stacks] + 1);
+                                next_frontier.push(next);
+                            }
+                        }
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+        distances
+    }
+
+    #[test]
+    fn shortest_moves_match_brute_force() {
+        let initial = Stacks(vec![vec!['A', 'B'], vec!['C'], vec!['D']]);
+        let cranes: [&mut dyn Crane; 3] = [
+            &mut Crane9000,
+            &mut Crane9001,
+            &mut LimitedCapacityCrane {
+                capacity: NonZeroUsize::new(2).unwrap(),
+            },
+        ];
+        for crane in cranes {
+            let distances = brute_force_distances(crane, &initial);
+            assert_eq!(distances.len(), 4 * 3 * 2 * 15);
+            for (target, &distance) in &distances {
+                let moves = find_shortest_moves(crane, &initial, target).unwrap();
+                assert_eq!(moves.len(), distance, "{}: {:?}", crane.name(), target);
+                let (reached, _) = run_crane(crane, &initial, &moves).unwrap();
+                assert_eq!(reached, *target);
+                assert!(is_optimal(crane, &initial, &moves));
+            }
+        }
+    }
+
+    #[test]
+    fn optimality_of_example_moves() {
+        let example = Day5Input::from_input(EXAMPLE).unwrap();
+        let cranes: [&mut dyn Crane; 3] = [
+            &mut Crane9000,
+            &mut Crane9001,
+            &mut LimitedCapacityCrane {
+                capacity: NonZeroUsize::new(2).unwrap(),
+            },
+        ];
+        for crane in cranes {
+            let distances = brute_force_distances(crane, &example.initial_stacks);
+            let (target, _) =
+                run_crane(crane, &example.initial_stacks, &example.move_list).unwrap();
+            assert_eq!(
+                is_optimal(crane, &example.initial_stacks, &example.move_list),
+                distances[&target] == example.move_list.len()
+            );
+        }
+        let mut wasteful = example.move_list.clone();
+        wasteful.extend([
+            StackMove {
+                from: 1,
+                to: 2,
+                crates: 1,
+                line: None,
+            },
+            StackMove {
+                from: 2,
+                to: 1,
+                crates: 1,
+                line: None,
+            },
+        ]);
+        assert!(!is_optimal(
+            &mut Crane9001,
+            &example.initial_stacks,
+            &wasteful
+        ));
+    }
+
+    #[test]
+    fn stateful_cranes_are_not_searched() {
+        let example = Day5Input::from_input(EXAMPLE).unwrap();
+        let mut crane = crane_from_name("alternating").unwrap();
+        let stacks = &example.initial_stacks;
+        assert!(find_shortest_moves(crane.as_mut(), stacks, stacks).is_none());
+        assert!(!is_optimal(crane.as_mut(), stacks, &example.move_list));
+    }
+
+    #[test]
+    fn errors_for_generated_moves_have_no_line() {
+        let stack_move = StackMove {
+            from: 1,
+            to: 4,
+            crates: 1,
+            line: None,
+        };
+        let error = Day5Input::from_input(EXAMPLE)
+            .unwrap()
+            .initial_stacks
+            .validate_move(&stack_move)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\"move 1 from 1 to 4\": there is no stack 4"
+        );
     }
-    let top_elements = stacks.0.iter().map(|stack| stack.last().unwrap()).join("");
-    println!("{:?}", top_elements);
 }
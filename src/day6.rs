@@ -1,19 +1,345 @@
-use iterslide::SlideIterator;
 use itertools::Itertools;
-use std::fs;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Bytes, ErrorKind, Read, Write};
 
-fn get_marker_position(file_contents: &str, window_size: usize) -> Option<usize> {
-    for (i, window) in file_contents.chars().slide(window_size).enumerate() {
-        if window.iter().unique().count() == window_size {
-            return Some(i + window_size);
+// Decodes the UTF-8 characters of a byte stream one at a time.
+struct Utf8Chars<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+}
+
+impl<R: Read> Iterator for Utf8Chars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let invalid = || io::Error::new(ErrorKind::InvalidData, "stream is not valid UTF-8");
+        let first = match self.bytes.next()? {
+            Ok(byte) => byte,
+            Err(e) => return Some(Err(e)),
+        };
+        let len = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some(Err(invalid())),
+        };
+        let mut buffer = [first, 0, 0, 0];
+        for byte in &mut buffer[1..len] {
+            *byte = match self.bytes.next() {
+                Some(Ok(byte)) => byte,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return Some(Err(invalid())),
+            };
+        }
+        Some(
+            std::str::from_utf8(&buffer[..len])
+                .map(|c| c.chars().next().unwrap())
+                .map_err(|_| invalid()),
+        )
+    }
+}
+
+// Keeps per-character counts for the last `window_size` characters, so each character is
+// processed in O(1).
+struct MarkerDetector {
+    window_size: usize,
+    max_duplicates: usize,
+    window: VecDeque<char>,
+    counts: HashMap<char, usize>,
+    distinct: usize,
+    position: usize,
+}
+
+impl MarkerDetector {
+    fn new(window_size: usize, max_duplicates: usize) -> MarkerDetector {
+        assert!(window_size > 0);
+        MarkerDetector {
+            window_size,
+            max_duplicates,
+            window: VecDeque::with_capacity(window_size),
+            counts: HashMap::new(),
+            distinct: 0,
+            position: 0,
+        }
+    }
+
+    // Returns whether the last `window_size` characters, ending with `c`, contain at most
+    // `max_duplicates` repeated characters.
+    fn push(&mut self, c: char) -> bool {
+        if self.window.len() == self.window_size {
+            let old = self.window.pop_front().unwrap();
+            let count = self.counts.get_mut(&old).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&old);
+                self.distinct -= 1;
+            }
         }
+        self.window.push_back(c);
+        let count = self.counts.entry(c).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            self.distinct += 1;
+        }
+        self.position += 1;
+        self.window.len() == self.window_size
+            && self.window_size - self.distinct <= self.max_duplicates
+    }
+
+    // Forgets the current window, so the next marker cannot overlap the previous one.
+    fn reset(&mut self) {
+        self.window.clear();
+        self.counts.clear();
+        self.distinct = 0;
+    }
+}
+
+// Yields the end offset of every marker window in the stream, counted in characters.
+struct Markers<R: Read> {
+    chars: Utf8Chars<R>,
+    detector: MarkerDetector,
+    overlapping: bool,
+}
+
+impl<R: Read> Markers<R> {
+    fn new(reader: R, window_size: usize, max_duplicates: usize, overlapping: bool) -> Markers<R> {
+        Markers {
+            chars: Utf8Chars {
+                bytes: BufReader::with_capacity(1 << 16, reader).bytes(),
+            },
+            detector: MarkerDetector::new(window_size, max_duplicates),
+            overlapping,
+        }
+    }
+}
+
+impl<R: Read> Iterator for Markers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for c in self.chars.by_ref() {
+            match c {
+                Ok(c) => {
+                    if self.detector.push(c) {
+                        if !self.overlapping {
+                            self.detector.reset();
+                        }
+                        return Some(Ok(self.detector.position));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    marker_start: usize,
+    payload_start: usize,
+    payload_end: usize,
+}
+
+fn find_marker<R: Read>(reader: R, window_size: usize) -> io::Result<Option<usize>> {
+    Markers::new(reader, window_size, 0, false)
+        .next()
+        .transpose()
+}
+
+// Splits the stream into non-overlapping packets, each one a marker followed by its payload,
+// which runs until the next marker starts or the stream ends.
+fn segment_stream<R: Read>(
+    reader: R,
+    window_size: usize,
+    max_duplicates: usize,
+) -> io::Result<Vec<Segment>> {
+    let mut markers = Markers::new(reader, window_size, max_duplicates, false);
+    let marker_ends = markers.by_ref().collect::<io::Result<Vec<_>>>()?;
+    let stream_length = markers.detector.position;
+    Ok(marker_ends
+        .iter()
+        .enumerate()
+        .map(|(i, &marker_end)| Segment {
+            marker_start: marker_end - window_size,
+            payload_start: marker_end,
+            payload_end: marker_ends
+                .get(i + 1)
+                .map_or(stream_length, |next_end| next_end - window_size),
+        })
+        .collect_vec())
+}
+
+fn write_offsets<W: Write>(mut writer: W, offsets: &[usize]) -> io::Result<()> {
+    for offset in offsets {
+        writeln!(writer, "{}", offset)?;
     }
-    None
+    Ok(())
 }
 
 #[allow(dead_code)]
 pub fn day_6() {
-    let file_contents = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
-    println!("{:?}", get_marker_position(&file_contents, 4).unwrap());
-    println!("{:?}", get_marker_position(&file_contents, 14).unwrap());
+    let open = || File::open("day6_puzzle.txt").expect("Unable to read file");
+    println!("{:?}", find_marker(open(), 4).unwrap().unwrap());
+    println!("{:?}", find_marker(open(), 14).unwrap().unwrap());
+
+    let all_markers = Markers::new(open(), 14, 0, true)
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    println!("start-of-message markers: {}", all_markers.len());
+    write_offsets(
+        io::stdout().lock(),
+        &all_markers[..all_markers.len().min(5)],
+    )
+    .unwrap();
+
+    let packets = segment_stream(open(), 14, 0).unwrap();
+    println!("packets: {}, first: {:?}", packets.len(), packets.first());
+    let near_markers = Markers::new(open(), 14, 1, true).count();
+    println!("windows with at most one duplicate: {}", near_markers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // End offsets of the windows with at most `max_duplicates` repeated characters, checking
+    // every window from scratch.
+    fn brute_force_markers(text: &str, window_size: usize, max_duplicates: usize) -> Vec<usize> {
+        let chars = text.chars().collect_vec();
+        chars
+            .windows(window_size)
+            .enumerate()
+            .filter(|(_, window)| window_size - window.iter().unique().count() <= max_duplicates)
+            .map(|(i, _)| i + window_size)
+            .collect_vec()
+    }
+
+    fn markers(text: &str, window_size: usize, max_duplicates: usize) -> Vec<usize> {
+        Markers::new(text.as_bytes(), window_size, max_duplicates, true)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn first_marker_matches_brute_force() {
+        let puzzle = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
+        for text in [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "bvwbjplbgvbhsrlpgdmjqwftvncz",
+            "nppdvjthqldpwncqszvftbrmjlhg",
+            "nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+            "zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+            "ääöäüßöüéé€x€€😀y😀",
+            &puzzle,
+        ] {
+            for window_size in [1, 4, 14] {
+                assert_eq!(
+                    find_marker(text.as_bytes(), window_size).unwrap(),
+                    brute_force_markers(text, window_size, 0).first().copied(),
+                    "{} {}",
+                    text,
+                    window_size
+                );
+            }
+        }
+        assert_eq!(find_marker("ää€😀é".as_bytes(), 4).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn overlapping_markers_match_brute_force() {
+        let puzzle = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
+        for text in [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "ääöäüßöüéé€x€€😀y😀",
+            &puzzle,
+        ] {
+            for window_size in [1, 4, 14] {
+                assert_eq!(
+                    markers(text, window_size, 0),
+                    brute_force_markers(text, window_size, 0)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn markers_with_duplicates_match_brute_force() {
+        let puzzle = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
+        for text in [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "ääöäüßöüéé€x€€😀y😀",
+            &puzzle,
+        ] {
+            for (window_size, max_duplicates) in [(4, 1), (4, 2), (14, 1), (14, 3)] {
+                assert_eq!(
+                    markers(text, window_size, max_duplicates),
+                    brute_force_markers(text, window_size, max_duplicates)
+                );
+            }
+        }
+        assert_eq!(markers("aabb", 2, 1), [2, 3, 4]);
+        assert_eq!(markers("aaab", 3, 1), [4]);
+    }
+
+    #[test]
+    fn non_overlapping_markers_start_after_the_previous_one() {
+        let puzzle = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
+        for (window_size, max_duplicates) in [(4, 0), (14, 0), (14, 2)] {
+            let expected = brute_force_markers(&puzzle, window_size, max_duplicates)
+                .into_iter()
+                .fold(Vec::new(), |mut ends: Vec<usize>, end| {
+                    if ends.last().is_none_or(|&last| end - window_size >= last) {
+                        ends.push(end);
+                    }
+                    ends
+                });
+            let ends = Markers::new(puzzle.as_bytes(), window_size, max_duplicates, false)
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(ends, expected);
+        }
+    }
+
+    #[test]
+    fn segments_run_from_marker_to_marker() {
+        assert_eq!(
+            segment_stream("abcdaaaaefghbb".as_bytes(), 4, 0).unwrap(),
+            [
+                Segment {
+                    marker_start: 0,
+                    payload_start: 4,
+                    payload_end: 7,
+                },
+                Segment {
+                    marker_start: 7,
+                    payload_start: 11,
+                    payload_end: 14,
+                },
+            ]
+        );
+        assert_eq!(segment_stream("aaaa".as_bytes(), 4, 0).unwrap(), []);
+
+        let puzzle = fs::read_to_string("day6_puzzle.txt").expect("Unable to read file");
+        let segments = segment_stream(puzzle.as_bytes(), 4, 1).unwrap();
+        assert!(segments.len() > 1);
+        for (segment, next) in segments.iter().tuple_windows() {
+            assert_eq!(segment.payload_end, next.marker_start);
+        }
+        for segment in &segments {
+            assert_eq!(segment.payload_start - segment.marker_start, 4);
+            assert!(segment.payload_start <= segment.payload_end);
+        }
+        assert_eq!(segments.last().unwrap().payload_end, puzzle.chars().count());
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let error = find_marker(&b"ab\xffcd"[..], 4).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(find_marker(&b"abc\xc3"[..], 4).is_err());
+    }
 }
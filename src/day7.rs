@@ -1,114 +1,483 @@
 use itertools::Itertools;
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::fs;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::{env, fs, io};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    File(usize),
+    Directory,
+}
+
+#[derive(Debug, Clone)]
 struct FileTree {
-    root_size: Option<usize>,
+    path: Vec<String>,
+    kind: FileKind,
     children: HashMap<String, FileTree>,
+    // Filled in by `size` and `directory_summaries`, cleared by `add_child` and `get_mut`.
+    cached_size: Cell<Option<usize>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum FileCommand {
     Cd(String),
     Ls(Vec<(Option<usize>, String)>),
+    Mkdir(String),
+    Touch(String, usize),
+    Rm(String),
+    Mv(String, String),
+    Du(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ShellError {
+    UnknownCommand(String),
+    MissingArgument(String),
+    NoSuchFileOrDirectory(String),
+    NotADirectory(String),
+    AlreadyExists(String),
+    InvalidTarget(String),
+    InvalidListing(String),
+}
+
+impl Display for ShellError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::UnknownCommand(command) => write!(f, "{}: command not found", command),
+            ShellError::MissingArgument(command) => write!(f, "{}: missing operand", command),
+            ShellError::NoSuchFileOrDirectory(path) => {
+                write!(f, "{}: No such file or directory", path)
+            }
+            ShellError::NotADirectory(path) => write!(f, "{}: Not a directory", path),
+            ShellError::AlreadyExists(path) => write!(f, "{}: File exists", path),
+            ShellError::InvalidTarget(path) => write!(f, "{}: Invalid target", path),
+            ShellError::InvalidListing(line) => write!(f, "{}: Invalid ls output", line),
+        }
+    }
 }
 
+// Flags other shells commonly pass, which change nothing here.
+const IGNORED_FLAGS: &[(&str, &str)] = &[
+    ("rm", "-r"),
+    ("rm", "-f"),
+    ("rm", "-rf"),
+    ("mkdir", "-p"),
+    ("du", "-h"),
+    ("du", "-s"),
+    ("du", "-sh"),
+];
+
 impl FileCommand {
-    fn parse_commands(lines: &[&str]) -> Vec<FileCommand> {
+    fn parse_commands(lines: &[&str]) -> Result<Vec<FileCommand>, ShellError> {
         let mut commands = Vec::new();
         let mut iter = lines.iter().peekable();
-        loop {
-            let next_command = if let Some(line) = iter.next() {
-                if line.starts_with("$ cd") {
-                    let path = line.chars().dropping(5).as_str();
-                    FileCommand::Cd(path.to_string())
-                } else {
-                    let mut output_lines = Vec::new();
-                    while let Some(i) = iter.next_if(|line| !line.starts_with("$")) {
-                        output_lines.push(*i);
+        while let Some(line) = iter.next() {
+            let mut output_lines = Vec::new();
+            while let Some(i) = iter.next_if(|line| !line.starts_with('$')) {
+                output_lines.push(*i);
+            }
+            let words = line
+                .trim_start_matches('$')
+                .split_ascii_whitespace()
+                .collect_vec();
+            let (&command, arguments) = words
+                .split_first()
+                .ok_or_else(|| ShellError::UnknownCommand(line.to_string()))?;
+            // Everything after `--` is an operand, even if it looks like a flag.
+            let mut end_of_flags = false;
+            let arguments = arguments
+                .iter()
+                .filter(|&&argument| {
+                    if end_of_flags {
+                        return true;
                     }
-                    let output = output_lines
+                    end_of_flags = argument == "--";
+                    !end_of_flags && !IGNORED_FLAGS.contains(&(command, argument))
+                })
+                .map(|argument| argument.to_string())
+                .collect_vec();
+            let argument = |i: usize| {
+                arguments
+                    .get(i)
+                    .cloned()
+                    .ok_or_else(|| ShellError::MissingArgument(command.to_string()))
+            };
+            let next_command = match command {
+                // Directory names may contain spaces or start with `-`, so `cd` takes the rest of
+                // the line as is.
+                "cd" => FileCommand::Cd(
+                    line.trim_start_matches('$')
+                        .trim_start()
+                        .strip_prefix("cd ")
+                        .ok_or_else(|| ShellError::MissingArgument(command.to_string()))?
+                        .to_string(),
+                ),
+                "ls" => FileCommand::Ls(
+                    output_lines
                         .into_iter()
-                        .map(|line| {
-                            let (size, name) = line.split_once(" ").unwrap();
-                            (size.parse().ok(), name.to_string())
+                        .map(|line| match line.split_once(' ') {
+                            Some(("dir", name)) => Ok((None, name.to_string())),
+                            Some((size, name)) => size
+                                .parse()
+                                .map(|size| (Some(size), name.to_string()))
+                                .map_err(|_| ShellError::InvalidListing(line.to_string())),
+                            None => Err(ShellError::InvalidListing(line.to_string())),
                         })
-                        .collect_vec();
-                    FileCommand::Ls(output)
-                }
-            } else {
-                break;
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                "mkdir" => FileCommand::Mkdir(argument(0)?),
+                "touch" => FileCommand::Touch(
+                    argument(0)?,
+                    argument(1)
+                        .ok()
+                        .and_then(|size| size.parse().ok())
+                        .unwrap_or(0),
+                ),
+                "rm" => FileCommand::Rm(argument(0)?),
+                "mv" => FileCommand::Mv(argument(0)?, argument(1)?),
+                "du" => FileCommand::Du(argument(0).ok()),
+                _ => return Err(ShellError::UnknownCommand(command.to_string())),
             };
             commands.push(next_command);
         }
-        commands
+        Ok(commands)
+    }
+}
+
+struct Shell {
+    tree: FileTree,
+    current_path: Vec<String>,
+}
+
+impl Shell {
+    fn new() -> Shell {
+        Shell {
+            tree: FileTree::new(Vec::new(), FileKind::Directory),
+            current_path: Vec::new(),
+        }
+    }
+
+    fn resolve(&self, path: &str) -> Vec<String> {
+        let mut result = if path.starts_with('/') {
+            Vec::new()
+        } else {
+            self.current_path.clone()
+        };
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    result.pop();
+                }
+                _ => result.push(segment.to_string()),
+            }
+        }
+        result
+    }
+
+    fn directory(&self, path: &[String], display_path: &str) -> Result<&FileTree, ShellError> {
+        let node = self
+            .tree
+            .get(path)
+            .ok_or_else(|| ShellError::NoSuchFileOrDirectory(display_path.to_string()))?;
+        if node.kind != FileKind::Directory {
+            return Err(ShellError::NotADirectory(display_path.to_string()));
+        }
+        Ok(node)
+    }
+
+    fn parent_directory(
+        &mut self,
+        path: &[String],
+        display_path: &str,
+    ) -> Result<(&mut FileTree, String), ShellError> {
+        let (name, parent_path) = path
+            .split_last()
+            .ok_or_else(|| ShellError::InvalidTarget(display_path.to_string()))?;
+        self.directory(parent_path, display_path)?;
+        Ok((self.tree.get_mut(parent_path).unwrap(), name.clone()))
+    }
+
+    // Executes a command and returns what it would print.
+    fn execute(&mut self, command: &FileCommand) -> Result<String, ShellError> {
+        match command {
+            FileCommand::Cd(path) => {
+                let path_segments = self.resolve(path);
+                self.directory(&path_segments, path)?;
+                self.current_path = path_segments;
+                Ok(String::new())
+            }
+            FileCommand::Ls(children) => {
+                let directory = self.tree.get_mut(&self.current_path).ok_or_else(|| {
+                    ShellError::NoSuchFileOrDirectory(format_path(&self.current_path))
+                })?;
+                for (size, name) in children {
+                    let kind = size.map_or(FileKind::Directory, FileKind::File);
+                    directory.add_child(name, kind);
+                }
+                Ok(directory
+                    .children
+                    .iter()
+                    .sorted_by_key(|(name, _)| *name)
+                    .map(|(name, child)| match child.kind {
+                        FileKind::File(size) => format!("{} {}", size, name),
+                        FileKind::Directory => format!("dir {}", name),
+                    })
+                    .join("\n"))
+            }
+            FileCommand::Mkdir(path) => {
+                let (parent, name) = self.parent_directory(&self.resolve(path), path)?;
+                if parent.children.contains_key(&name) {
+                    return Err(ShellError::AlreadyExists(path.clone()));
+                }
+                parent.add_child(&name, FileKind::Directory);
+                Ok(String::new())
+            }
+            FileCommand::Touch(path, size) => {
+                let (parent, name) = self.parent_directory(&self.resolve(path), path)?;
+                if parent.add_child(&name, FileKind::File(*size)).kind != FileKind::File(*size) {
+                    return Err(ShellError::AlreadyExists(path.clone()));
+                }
+                Ok(String::new())
+            }
+            FileCommand::Rm(path) => {
+                let path_segments = self.resolve(path);
+                let (parent, name) = self.parent_directory(&path_segments, path)?;
+                parent
+                    .children
+                    .remove(&name)
+                    .ok_or_else(|| ShellError::NoSuchFileOrDirectory(path.clone()))?;
+                if self.current_path.starts_with(&path_segments) {
+                    self.current_path = path_segments[..path_segments.len() - 1].to_vec();
+                }
+                Ok(String::new())
+            }
+            FileCommand::Mv(source, target) => {
+                let source_segments = self.resolve(source);
+                let mut target_segments = self.resolve(target);
+                if self.tree.get(&source_segments).is_none() {
+                    return Err(ShellError::NoSuchFileOrDirectory(source.clone()));
+                }
+                if self.directory(&target_segments, target).is_ok() {
+                    target_segments.push(source_segments.last().cloned().unwrap_or_default());
+                }
+                if target_segments.starts_with(&source_segments) {
+                    return Err(ShellError::InvalidTarget(target.clone()));
+                }
+                if self.tree.get(&target_segments).is_some() {
+                    return Err(ShellError::AlreadyExists(target.clone()));
+                }
+                self.parent_directory(&target_segments, target)?;
+                let (source_parent, source_name) =
+                    self.parent_directory(&source_segments, source)?;
+                let mut node = source_parent.children.remove(&source_name).unwrap();
+                node.set_path(target_segments.clone());
+                let (target_parent, target_name) =
+                    self.parent_directory(&target_segments, target)?;
+                target_parent.children.insert(target_name, node);
+                if self.current_path.starts_with(&source_segments) {
+                    self.current_path = target_segments
+                        .iter()
+                        .chain(&self.current_path[source_segments.len()..])
+                        .cloned()
+                        .collect_vec();
+                }
+                Ok(String::new())
+            }
+            FileCommand::Du(path) => {
+                let path = path.clone().unwrap_or_else(|| ".".to_string());
+                let node = self
+                    .tree
+                    .get(&self.resolve(&path))
+                    .ok_or_else(|| ShellError::NoSuchFileOrDirectory(path.clone()))?;
+                Ok(format!("{}\t{}", node.size(), path))
+            }
+        }
     }
 }
 
 impl FileTree {
     fn size(&self) -> usize {
+        if let Some(size) = self.cached_size.get() {
+            return size;
+        }
         let children_sizes = self
             .children
             .values()
             .map(|subtree| subtree.size())
             .sum::<usize>();
-        children_sizes + self.root_size.unwrap_or(0)
+        let size = match self.kind {
+            FileKind::File(size) => size + children_sizes,
+            FileKind::Directory => children_sizes,
+        };
+        self.cached_size.set(Some(size));
+        size
+    }
+
+    fn recursive_walk(&self) -> FileTreeWalker<'_> {
+        FileTreeWalker { stack: vec![self] }
+    }
+
+    // Sizes of all directories, computed bottom-up in a single pass; parents come after children.
+    fn directory_summaries(&self) -> Vec<DirectorySummary> {
+        let mut result = Vec::new();
+        self.summarize(&mut result);
+        result
+    }
+
+    fn summarize(&self, result: &mut Vec<DirectorySummary>) -> (usize, usize, usize) {
+        if let FileKind::File(size) = self.kind {
+            return (size, 1, 0);
+        }
+        let (mut size, mut file_count, mut directory_count) = (0, 0, 0);
+        for (_, child) in self.children.iter().sorted_by_key(|(name, _)| *name) {
+            let child_summary = child.summarize(result);
+            size += child_summary.0;
+            file_count += child_summary.1;
+            directory_count += child_summary.2;
+        }
+        self.cached_size.set(Some(size));
+        result.push(DirectorySummary {
+            path: self.path.clone(),
+            size,
+            file_count,
+            directory_count,
+        });
+        (size, file_count, directory_count + 1)
+    }
+
+    fn from_disk(directory: &Path) -> io::Result<FileTree> {
+        let mut tree = FileTree::new(Vec::new(), FileKind::Directory);
+        tree.read_disk_directory(directory)?;
+        Ok(tree)
+    }
+
+    fn read_disk_directory(&mut self, directory: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Symlinks are skipped so that link cycles cannot recurse forever.
+            let metadata = entry.path().symlink_metadata()?;
+            if metadata.is_dir() {
+                self.add_child(&name, FileKind::Directory)
+                    .read_disk_directory(&entry.path())?;
+            } else if metadata.is_file() {
+                self.add_child(&name, FileKind::File(metadata.len() as usize));
+            }
+        }
+        Ok(())
+    }
+
+    // The `$ cd` / `$ ls` session that would discover this tree.
+    fn to_transcript(&self) -> String {
+        let mut lines = vec!["$ cd /".to_string()];
+        self.write_transcript(&mut lines);
+        lines.join("\n")
     }
 
-    fn recursive_walk(&self) -> FileTreeWalker {
-        FileTreeWalker { stack: vec![&self] }
+    fn write_transcript(&self, lines: &mut Vec<String>) {
+        let children = self
+            .children
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+            .collect_vec();
+        lines.push("$ ls".to_string());
+        for (name, child) in children.iter() {
+            lines.push(match child.kind {
+                FileKind::File(size) => format!("{} {}", size, name),
+                FileKind::Directory => format!("dir {}", name),
+            });
+        }
+        for (name, child) in children {
+            if child.kind == FileKind::Directory {
+                lines.push(format!("$ cd {}", name));
+                child.write_transcript(lines);
+                lines.push("$ cd ..".to_string());
+            }
+        }
     }
 
-    fn from_input(file_contents: &str) -> FileTree {
+    // Directories in pre-order with their sizes and the index just past their subtree.
+    fn directories_preorder<'a>(&'a self, result: &mut Vec<(usize, usize, &'a FileTree)>) -> usize {
+        if let FileKind::File(size) = self.kind {
+            return size;
+        }
+        let index = result.len();
+        result.push((0, 0, self));
+        let size = self
+            .children
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+            .map(|(_, child)| child.directories_preorder(result))
+            .sum();
+        result[index] = (size, result.len(), self);
+        size
+    }
+
+    fn new(path: Vec<String>, kind: FileKind) -> FileTree {
+        FileTree {
+            path,
+            kind,
+            children: HashMap::new(),
+            cached_size: Cell::new(None),
+        }
+    }
+
+    fn add_child(&mut self, name: &str, kind: FileKind) -> &mut FileTree {
+        self.cached_size.set(None);
+        let path = self
+            .path
+            .iter()
+            .cloned()
+            .chain([name.to_string()])
+            .collect_vec();
+        self.children
+            .entry(name.to_string())
+            .or_insert_with(|| FileTree::new(path, kind))
+    }
+
+    fn set_path(&mut self, path: Vec<String>) {
+        for (name, child) in self.children.iter_mut() {
+            child.set_path(path.iter().cloned().chain([name.clone()]).collect_vec());
+        }
+        self.path = path;
+    }
+
+    fn get(&self, path: &[String]) -> Option<&FileTree> {
+        path.iter()
+            .try_fold(self, |subtree, name| subtree.children.get(name))
+    }
+
+    // Changes made through the result can change the size of every directory on the path, so
+    // their cached sizes are dropped.
+    fn get_mut(&mut self, path: &[String]) -> Option<&mut FileTree> {
+        self.cached_size.set(None);
+        path.iter().try_fold(self, |subtree, name| {
+            let child = subtree.children.get_mut(name)?;
+            child.cached_size.set(None);
+            Some(child)
+        })
+    }
+
+    fn from_input(file_contents: &str) -> Result<FileTree, ShellError> {
         let lines = file_contents
-            .split("\n")
+            .split('\n')
             .filter(|line| !line.is_empty())
             .collect_vec();
 
-        let commands = FileCommand::parse_commands(&lines);
+        let commands = FileCommand::parse_commands(&lines)?;
 
         FileTree::from_commands(&commands)
     }
 
-    fn from_commands(commands: &[FileCommand]) -> FileTree {
-        let mut tree = FileTree {
-            root_size: None,
-            children: HashMap::new(),
-        };
-        let mut current_path = Vec::new();
-
+    fn from_commands(commands: &[FileCommand]) -> Result<FileTree, ShellError> {
+        let mut shell = Shell::new();
         for command in commands {
-            match command {
-                FileCommand::Cd(path) => match path.as_str() {
-                    "/" => current_path = Vec::new(),
-                    ".." => {
-                        current_path.pop();
-                        ()
-                    }
-                    _ => current_path.push(path),
-                },
-                FileCommand::Ls(children) => {
-                    let mut subtree = &mut tree;
-                    for &p in current_path.iter() {
-                        subtree = subtree.children.get_mut(p).unwrap();
-                    }
-                    for child in children {
-                        if !subtree.children.contains_key(&child.1) {
-                            subtree.children.insert(
-                                child.1.clone(),
-                                FileTree {
-                                    root_size: child.0,
-                                    children: HashMap::new(),
-                                },
-                            );
-                        }
-                    }
-                }
-            }
+            shell.execute(command)?;
         }
-
-        tree
+        Ok(shell.tree)
     }
 }
 
@@ -117,51 +486,649 @@ struct FileTreeWalker<'a> {
 }
 
 impl<'a> Iterator for FileTreeWalker<'a> {
-    type Item = &'a FileTree;
+    type Item = (String, &'a FileTree);
 
     fn next(&mut self) -> Option<Self::Item> {
         let next_subtree = self.stack.pop()?;
         self.stack.extend(next_subtree.children.values());
-        Some(next_subtree)
+        Some((format_path(&next_subtree.path), next_subtree))
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DirectorySummary {
+    path: Vec<String>,
+    size: usize,
+    file_count: usize,
+    directory_count: usize,
+}
+
+fn sum_of_directories_up_to(summaries: &[DirectorySummary], threshold: usize) -> usize {
+    summaries
+        .iter()
+        .map(|summary| summary.size)
+        .filter(|&size| size <= threshold)
+        .sum()
+}
+
+// Sets of reachable sizes below a bound, one bit per size.
+#[derive(Debug, Clone)]
+struct SizeSet {
+    words: Vec<u64>,
+    bound: usize,
+}
+
+impl SizeSet {
+    fn new(bound: usize) -> SizeSet {
+        SizeSet {
+            words: vec![0; bound.div_ceil(64)],
+            bound,
+        }
+    }
+
+    fn contains(&self, size: usize) -> bool {
+        size < self.bound && self.words[size / 64] & (1 << (size % 64)) != 0
+    }
+
+    fn insert(&mut self, size: usize) {
+        if size < self.bound {
+            self.words[size / 64] |= 1 << (size % 64);
+        }
+    }
+
+    // Adds `size + s` for every `s` in `other`, dropping sums that reach the bound.
+    fn insert_shifted(&mut self, other: &SizeSet, shift: usize) {
+        let (word_shift, bit_shift) = (shift / 64, shift % 64);
+        for i in (word_shift..self.words.len()).rev() {
+            let mut word = other.words[i - word_shift] << bit_shift;
+            if bit_shift > 0 && i > word_shift {
+                word |= other.words[i - word_shift - 1] >> (64 - bit_shift);
+            }
+            self.words[i] |= word;
+        }
+        if !self.bound.is_multiple_of(64) {
+            *self.words.last_mut().unwrap() &= (1 << (self.bound % 64)) - 1;
+        }
+    }
+
+    fn first_at_least(&self, size: usize) -> Option<usize> {
+        if size >= self.bound {
+            return None;
+        }
+        let first_word = self.words[size / 64] & (u64::MAX << (size % 64));
+        [first_word]
+            .into_iter()
+            .chain(self.words[size / 64 + 1..].iter().copied())
+            .enumerate()
+            .find(|(_, word)| *word != 0)
+            .map(|(i, word)| (size / 64 + i) * 64 + word.trailing_zeros() as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DiskConfig {
+    total_space: usize,
+    required_space: usize,
+}
+
+impl DiskConfig {
+    fn required_free_space(&self, used_space: usize) -> usize {
+        (self.required_space + used_space).saturating_sub(self.total_space)
+    }
+}
+
+// Upper bound on the bits kept in `SizeSet`s while planning a deletion, about 128 MB.
+const PLAN_MEMORY_BITS: usize = 1 << 30;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeletionPlan {
+    freed: usize,
+    deleted: Vec<String>,
+    // Sizes are counted in blocks of this many bytes; with 1 the plan is optimal.
+    block_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PlanError {
+    NotEnoughSpace(usize),
+    TooManyDirectories(usize),
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::NotEnoughSpace(required) => {
+                write!(f, "deleting everything frees less than {} bytes", required)
+            }
+            PlanError::TooManyDirectories(count) => {
+                write!(f, "too many directories to plan a deletion: {}", count)
+            }
+        }
+    }
+}
+
+// Picks non-nested directories freeing at least `required_free_space` bytes while deleting as
+// little as possible. Directories are visited in pre-order, where deleting one skips its subtree,
+// so this is a 0/1 knapsack over the sets of sizes reachable from each suffix.
+//
+// To bound memory, sizes are rounded down to blocks so that every set fits in its share of
+// `PLAN_MEMORY_BITS`. Rounding down keeps the plan valid; it may then delete up to one block per
+// directory more than needed, but never more than the best single directory.
+fn plan_deletion(
+    file_tree: &FileTree,
+    required_free_space: usize,
+) -> Result<DeletionPlan, PlanError> {
+    let mut directories = Vec::new();
+    file_tree.directories_preorder(&mut directories);
+    let n = directories.len();
+
+    let bits_per_set = PLAN_MEMORY_BITS / (n + 1);
+    if bits_per_set < 64 {
+        return Err(PlanError::TooManyDirectories(n));
+    }
+    let block_size = required_free_space.div_ceil(bits_per_set).max(1);
+    let required_blocks = required_free_space.div_ceil(block_size);
+    let blocks = |size: usize| size / block_size;
+
+    // below[i]: block sums under the requirement reachable from directories i..; at_least[i]: the
+    // smallest reachable block sum meeting it.
+    let mut below = vec![SizeSet::new(required_blocks); n + 1];
+    let mut at_least = vec![None; n + 1];
+    below[n].insert(0);
+    for i in (0..n).rev() {
+        let (size, subtree_end, _) = directories[i];
+        let size = blocks(size);
+        let mut reachable = below[i + 1].clone();
+        reachable.insert_shifted(&below[subtree_end], size);
+        below[i] = reachable;
+        at_least[i] = [
+            at_least[i + 1],
+            at_least[subtree_end].map(|freed| freed + size),
+            below[subtree_end]
+                .first_at_least(required_blocks.saturating_sub(size))
+                .map(|freed| freed + size),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+    }
+
+    let reachable_from =
+        |i: usize, remaining: usize| below[i].contains(remaining) || at_least[i] == Some(remaining);
+    let mut deleted = Vec::new();
+    if required_blocks > 0 {
+        if let Some(freed_blocks) = at_least[0] {
+            let (mut i, mut remaining) = (0, freed_blocks);
+            while remaining > 0 {
+                if reachable_from(i + 1, remaining) {
+                    i += 1;
+                } else {
+                    let (size, subtree_end, directory) = directories[i];
+                    deleted.push((size, directory));
+                    remaining -= blocks(size);
+                    i = subtree_end;
+                }
+            }
+        }
+    }
+    let mut freed = deleted.iter().map(|(size, _)| size).sum::<usize>();
+
+    // Rounding can hide a directory that is just big enough on its own.
+    let best_single = directories
+        .iter()
+        .filter(|(size, _, _)| *size >= required_free_space)
+        .min_by_key(|(size, _, _)| *size);
+    if let Some(&(size, _, directory)) = best_single {
+        if (deleted.is_empty() && required_free_space > 0) || size < freed {
+            deleted = vec![(size, directory)];
+            freed = size;
+        }
+    }
+    if freed < required_free_space {
+        return Err(PlanError::NotEnoughSpace(required_free_space));
+    }
+    Ok(DeletionPlan {
+        freed,
+        deleted: deleted
+            .into_iter()
+            .map(|(_, directory)| format_path(&directory.path))
+            .sorted()
+            .collect_vec(),
+        block_size,
+    })
+}
+
+fn smallest_directory_freeing(
+    summaries: &[DirectorySummary],
+    required_free_space: usize,
+) -> Option<&DirectorySummary> {
+    summaries
+        .iter()
+        .filter(|summary| summary.size >= required_free_space)
+        .min_by_key(|summary| summary.size)
+}
+
+fn format_path(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+// Rounds up like `du -h`, to one decimal below 10; rounding up can carry into the next unit.
+fn human_readable_size(size: usize) -> String {
+    let round_up = |value: f64| {
+        if value < 10.0 {
+            (value * 10.0).ceil() / 10.0
+        } else {
+            value.ceil()
+        }
+    };
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < 4 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit > 0 {
+        value = round_up(value);
+        if value >= 1024.0 && unit < 4 {
+            value = round_up(value / 1024.0);
+            unit += 1;
+        }
+    }
+    let suffix = ["", "K", "M", "G", "T"][unit];
+    if unit == 0 || value >= 10.0 {
+        format!("{}{}", value, suffix)
+    } else {
+        format!("{:.1}{}", value, suffix)
+    }
+}
+
+// Like `du -h`, largest directories first, hiding directories deeper than `max_depth`.
+fn du_report(summaries: &[DirectorySummary], max_depth: Option<usize>) -> String {
+    summaries
+        .iter()
+        .filter(|summary| max_depth.is_none_or(|depth| summary.path.len() <= depth))
+        .sorted_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)))
+        .map(|summary| {
+            format!(
+                "{:>6}\t{:>5} files\t{:>4} dirs\t{}",
+                human_readable_size(summary.size),
+                summary.file_count,
+                summary.directory_count,
+                format_path(&summary.path)
+            )
+        })
+        .join("\n")
+}
+
 #[allow(dead_code)]
 pub fn day_7() {
     let file_contents = fs::read_to_string("day7_puzzle.txt").expect("Unable to read file");
 
-    let file_tree = FileTree::from_input(&file_contents);
+    let file_tree =
+        FileTree::from_input(&file_contents).unwrap_or_else(|error| panic!("{}", error));
+
+    let summaries = file_tree.directory_summaries();
+    let root_size = summaries.last().unwrap().size;
 
     {
         // star 1
-        let mut star1_sum = 0;
-        for subtree in file_tree.recursive_walk() {
-            let subtree_size = subtree.size();
-            if subtree.root_size.is_none() && subtree_size <= 100000 {
-                star1_sum += subtree_size;
-            }
-        }
-        println!("{:?}", star1_sum);
+        println!("{:?}", sum_of_directories_up_to(&summaries, 100000));
+        println!(
+            "{}",
+            summaries
+                .iter()
+                .filter(|summary| summary.size <= 100000)
+                .map(|summary| format_path(&summary.path))
+                .join(", ")
+        );
     }
 
     // star 2
     {
-        const TOTAL_SPACE: usize = 70000000;
-        const TOTAL_REQUIRED_SPACE: usize = 30000000;
-        let mut best_size = file_tree.size();
-        let required_free_space = TOTAL_REQUIRED_SPACE - (TOTAL_SPACE - best_size);
-        for subtree in file_tree.recursive_walk() {
-            let subtree_size = subtree.size();
-            if subtree.root_size.is_none()
-                && subtree_size >= required_free_space
-                && subtree_size < best_size
-            {
-                best_size = subtree_size;
+        let disk_config = DiskConfig {
+            total_space: 70000000,
+            required_space: 30000000,
+        };
+        let required_free_space = disk_config.required_free_space(root_size);
+        let best = smallest_directory_freeing(&summaries, required_free_space).unwrap();
+        println!(
+            "free space needed: {:?}, solution: {:?} ({})",
+            required_free_space,
+            best.size,
+            format_path(&best.path)
+        );
+        let plan = plan_deletion(&file_tree, required_free_space).unwrap();
+        println!(
+            "deletion plan: {} bytes from {:?}",
+            plan.freed, plan.deleted
+        );
+    }
+
+    println!("{}", du_report(&summaries, Some(1)));
+    let largest_files = file_tree
+        .recursive_walk()
+        .filter_map(|(path, node)| match node.kind {
+            FileKind::File(size) => Some((size, path)),
+            FileKind::Directory => None,
+        })
+        .sorted()
+        .rev()
+        .take(3)
+        .collect_vec();
+    println!("largest files: {:?}", largest_files);
+
+    // A directory on disk given on the command line, optionally with total and required space
+    if let Some(directory) = env::args().nth(1) {
+        let disk_tree =
+            FileTree::from_disk(Path::new(&directory)).expect("Unable to read directory");
+        let disk_summaries = disk_tree.directory_summaries();
+        let disk_size = disk_summaries.last().unwrap().size;
+        let mut sizes = env::args()
+            .skip(2)
+            .map(|bytes| bytes.parse().expect("Invalid byte count"));
+        let disk_config = DiskConfig {
+            total_space: sizes.next().unwrap_or(disk_size),
+            required_space: sizes.next().unwrap_or(disk_size / 2),
+        };
+        let required_free_space = disk_config.required_free_space(disk_size);
+        println!("{}", du_report(&disk_summaries, Some(2)));
+        println!(
+            "directories up to 100000 bytes: {}",
+            sum_of_directories_up_to(&disk_summaries, 100000)
+        );
+        match smallest_directory_freeing(&disk_summaries, required_free_space) {
+            Some(best) => println!(
+                "smallest directory freeing {} bytes: {} ({})",
+                required_free_space,
+                format_path(&best.path),
+                human_readable_size(best.size)
+            ),
+            None => println!("no directory frees {} bytes", required_free_space),
+        }
+        match plan_deletion(&disk_tree, required_free_space) {
+            Ok(plan) => println!(
+                "deletion plan: {} bytes from {:?} (sizes counted in {} byte blocks)",
+                plan.freed, plan.deleted, plan.block_size
+            ),
+            Err(error) => println!("no deletion plan: {}", error),
+        }
+
+        let transcript = disk_tree.to_transcript();
+        println!("{}", transcript.lines().take(10).join("\n"));
+    }
+
+    // Replaying a transcript that uses the other shell commands
+    {
+        let transcript = [
+            "$ mkdir /a",
+            "$ mkdir a/b",
+            "$ touch a/b/f.txt 100",
+            "$ cd a/b",
+            "$ touch ../g.txt 50",
+            "$ du /a",
+            "$ mv /a/g.txt .",
+            "$ rm f.txt",
+            "$ ls",
+            "$ cd /a/b/g.txt",
+        ];
+        let mut shell = Shell::new();
+        for command in FileCommand::parse_commands(&transcript).unwrap() {
+            match shell.execute(&command) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(error) => println!("{}", error),
             }
         }
-        println!(
-            "free space needed: {:?}, solution: {:?}",
-            required_free_space, best_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn run_shell(transcript: &[&str]) -> (Shell, Vec<Result<String, ShellError>>) {
+        let mut shell = Shell::new();
+        let outputs = FileCommand::parse_commands(transcript)
+            .unwrap()
+            .iter()
+            .map(|command| shell.execute(command))
+            .collect_vec();
+        (shell, outputs)
+    }
+
+    #[test]
+    fn moving_an_ancestor_of_the_working_directory_follows_it() {
+        let (shell, outputs) = run_shell(&[
+            "$ mkdir /a",
+            "$ mkdir /a/b",
+            "$ touch /a/b/f.txt 10",
+            "$ cd /a/b",
+            "$ mv /a /c",
+            "$ ls",
+            "$ mv . /d",
+            "$ touch g.txt 5",
+        ]);
+        assert!(outputs.iter().all(|output| output.is_ok()));
+        assert_eq!(outputs[5], Ok("10 f.txt".to_string()));
+        assert_eq!(shell.current_path, ["d"]);
+        assert_eq!(shell.tree.get(&["d".to_string()]).unwrap().size(), 15);
+    }
+
+    #[test]
+    fn removing_the_working_directory_moves_to_its_parent() {
+        let (shell, outputs) = run_shell(&["$ mkdir /a", "$ mkdir /a/b", "$ cd /a/b", "$ rm /a"]);
+        assert!(outputs.iter().all(|output| output.is_ok()));
+        assert!(shell.current_path.is_empty());
+    }
+
+    // A fresh directory under the system temp directory, removed when dropped.
+    struct TempDirectory(PathBuf);
+
+    impl TempDirectory {
+        fn new(name: &str) -> TempDirectory {
+            let path = env::temp_dir().join(format!("day7-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDirectory(path)
+        }
+
+        fn add_file(&self, path: &str, size: usize) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; size]).unwrap();
+        }
+    }
+
+    impl Drop for TempDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn disk_tree_summaries_and_transcript_round_trip() {
+        let directory = TempDirectory::new("disk");
+        directory.add_file("a.txt", 100);
+        directory.add_file("my dir/b.txt", 20);
+        directory.add_file("my dir/ nested /c d.txt", 3);
+        directory.add_file("-x/e", 4000);
+        directory.add_file("$ cd ../f", 5);
+        fs::create_dir(directory.0.join("empty")).unwrap();
+
+        let tree = FileTree::from_disk(&directory.0).unwrap();
+        let summaries = tree
+            .directory_summaries()
+            .into_iter()
+            .map(|summary| (format_path(&summary.path), summary.size, summary.file_count))
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            summaries,
+            [
+                ("/".to_string(), 4128, 5),
+                ("/$ cd ..".to_string(), 5, 1),
+                ("/-x".to_string(), 4000, 1),
+                ("/empty".to_string(), 0, 0),
+                ("/my dir".to_string(), 23, 2),
+                ("/my dir/ nested ".to_string(), 3, 1),
+            ]
+        );
+
+        let replayed = FileTree::from_input(&tree.to_transcript()).unwrap();
+        assert_eq!(replayed.directory_summaries(), tree.directory_summaries());
+    }
+
+    fn check_plan(tree: &FileTree, required_free_space: usize) -> DeletionPlan {
+        let plan = plan_deletion(tree, required_free_space).unwrap();
+        let summaries = tree.directory_summaries();
+        let deleted = summaries
+            .iter()
+            .filter(|summary| plan.deleted.contains(&format_path(&summary.path)))
+            .collect_vec();
+        assert_eq!(deleted.len(), plan.deleted.len());
+        assert_eq!(
+            deleted.iter().map(|summary| summary.size).sum::<usize>(),
+            plan.freed
+        );
+        assert!(deleted
+            .iter()
+            .tuple_combinations()
+            .all(|(a, b)| !a.path.starts_with(&b.path) && !b.path.starts_with(&a.path)));
+        let best_single = smallest_directory_freeing(&summaries, required_free_space).unwrap();
+        assert!(required_free_space <= plan.freed && plan.freed <= best_single.size);
+        plan
+    }
+
+    #[test]
+    fn puzzle_deletion_plan_is_exact() {
+        let file_contents = fs::read_to_string("day7_puzzle.txt").expect("Unable to read file");
+        let tree = FileTree::from_input(&file_contents).unwrap();
+        let plan = check_plan(&tree, 1518953);
+        assert_eq!(plan.block_size, 1);
+        assert_eq!(plan.freed, 1518953);
+    }
+
+    #[test]
+    fn deletion_plan_for_huge_sizes_uses_blocks() {
+        let gigabyte = 1 << 30;
+        let mut tree = FileTree::new(Vec::new(), FileKind::Directory);
+        for (directory, size) in [("a", 7), ("b", 13), ("c", 21), ("d", 5)] {
+            tree.add_child(directory, FileKind::Directory)
+                .add_child("data", FileKind::File(size * gigabyte + 12345));
+        }
+        let plan = check_plan(&tree, 20 * gigabyte + 1);
+        assert!(plan.block_size > 1);
+        assert_eq!(plan.deleted, ["/a", "/b"]);
+        assert_eq!(
+            plan_deletion(&tree, 50 * gigabyte),
+            Err(PlanError::NotEnoughSpace(50 * gigabyte))
+        );
+    }
+
+    #[test]
+    fn only_known_flags_are_ignored() {
+        let (shell, outputs) = run_shell(&[
+            "$ mkdir -p /a",
+            "$ mkdir -x",
+            "$ mkdir -- -p",
+            "$ touch -x/f 3",
+            "$ rm -rf /a",
+            "$ ls",
+        ]);
+        assert!(outputs.iter().all(|output| output.is_ok()));
+        assert_eq!(outputs[5], Ok("dir -p\ndir -x".to_string()));
+        assert_eq!(shell.tree.size(), 3);
+    }
+
+    #[test]
+    fn touching_an_existing_entry_keeps_it_only_if_unchanged() {
+        let (shell, outputs) = run_shell(&[
+            "$ touch f 10",
+            "$ touch f 10",
+            "$ touch f 20",
+            "$ mkdir d",
+            "$ touch d 0",
+        ]);
+        assert_eq!(outputs[1], Ok(String::new()));
+        assert_eq!(outputs[2], Err(ShellError::AlreadyExists("f".to_string())));
+        assert_eq!(outputs[4], Err(ShellError::AlreadyExists("d".to_string())));
+        assert_eq!(shell.tree.size(), 10);
+    }
+
+    #[test]
+    fn malformed_listings_are_rejected() {
+        for line in ["12", "twelve f.txt", "-3 f.txt"] {
+            assert_eq!(
+                FileCommand::parse_commands(&["$ cd /", "$ ls", "dir a", line]).unwrap_err(),
+                ShellError::InvalidListing(line.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn human_readable_sizes_round_up_like_du() {
+        let cases = [
+            (0, "0"),
+            (1023, "1023"),
+            (1024, "1.0K"),
+            (1025, "1.1K"),
+            (10239, "10K"),
+            (10240, "10K"),
+            (10241, "11K"),
+            (1048000, "1.0M"),
+            (1048576, "1.0M"),
+            (1048577, "1.1M"),
+            (5 << 30, "5.0G"),
+        ];
+        for (size, expected) in cases {
+            assert_eq!(human_readable_size(size), expected, "{}", size);
+        }
+    }
+
+    #[test]
+    fn du_report_lists_largest_directories_first() {
+        let (shell, outputs) = run_shell(&[
+            "$ mkdir /a",
+            "$ mkdir /a/b",
+            "$ mkdir /c",
+            "$ touch /a/b/f 1048000",
+            "$ touch /a/g 500",
+            "$ touch /c/h 3000",
+            "$ touch /i 10",
+        ]);
+        assert!(outputs.iter().all(|output| output.is_ok()));
+        let summaries = shell.tree.directory_summaries();
+        assert_eq!(
+            du_report(&summaries, Some(1)),
+            [
+                "  1.1M\t    4 files\t   3 dirs\t/",
+                "  1.0M\t    2 files\t   1 dirs\t/a",
+                "  3.0K\t    1 files\t   0 dirs\t/c",
+            ]
+            .join("\n")
         );
+        assert_eq!(du_report(&summaries, Some(0)).lines().count(), 1);
+        assert_eq!(du_report(&summaries, None).lines().count(), 4);
+    }
+
+    #[test]
+    fn cached_sizes_follow_shell_changes() {
+        let (mut shell, _) = run_shell(&["$ mkdir /a", "$ mkdir /a/b", "$ touch /a/b/f 10"]);
+        let du = |shell: &mut Shell, path: &str| {
+            shell
+                .execute(&FileCommand::Du(Some(path.to_string())))
+                .unwrap()
+        };
+        assert_eq!(du(&mut shell, "/"), "10\t/");
+        shell.tree.directory_summaries();
+        for command in FileCommand::parse_commands(&["$ touch /a/b/g 5", "$ mv /a/b /c"]).unwrap() {
+            shell.execute(&command).unwrap();
+        }
+        assert_eq!(du(&mut shell, "/"), "15\t/");
+        assert_eq!(du(&mut shell, "/a"), "0\t/a");
+        assert_eq!(du(&mut shell, "/c"), "15\t/c");
+        shell.execute(&FileCommand::Rm("/c/f".to_string())).unwrap();
+        assert_eq!(du(&mut shell, "/"), "5\t/");
     }
 }
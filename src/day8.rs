@@ -1,18 +1,20 @@
-use std::cmp::max;
-use std::fs;
+use itertools::Itertools;
+use num::integer::gcd;
+use std::cmp::{max, Reverse};
+use std::ops::Range;
+use std::{fs, iter};
+
+fn parse_grid(file_contents: &str) -> Vec<Vec<u8>> {
+    file_contents
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| line.bytes().map(|c| c - b'0').collect())
+        .collect()
+}
 
 fn read_input() -> Vec<Vec<u8>> {
     let file_contents = fs::read_to_string("day8_puzzle.txt").expect("Unable to read file");
-    let mut result = vec![Vec::new()];
-    for c in file_contents.chars() {
-        if c == '\n' {
-            result.push(Vec::new());
-        } else {
-            let last_row = result.last_mut().unwrap();
-            last_row.push((c as u8) - b'0');
-        }
-    }
-    result
+    parse_grid(&file_contents)
 }
 
 enum Rotation {
@@ -22,30 +24,34 @@ enum Rotation {
     D270,
 }
 
-fn rotate_tree_grid<T: Default + Clone>(
-    tree_grid: &Vec<Vec<T>>,
-    rotation: Rotation,
-) -> Vec<Vec<T>> {
-    let n = tree_grid.len();
-    let mut result = vec![vec![T::default(); n]; n];
-    for r in 0..n {
-        for c in 0..n {
+fn rotate_tree_grid<T: Default + Clone>(tree_grid: &[Vec<T>], rotation: Rotation) -> Vec<Vec<T>> {
+    let rows = tree_grid.len();
+    let cols = tree_grid.first().map_or(0, |row| row.len());
+    let (new_rows, new_cols) = match rotation {
+        Rotation::D0 | Rotation::D180 => (rows, cols),
+        Rotation::D90 | Rotation::D270 => (cols, rows),
+    };
+    let mut result = vec![vec![T::default(); new_cols]; new_rows];
+    for (r, row) in tree_grid.iter().enumerate() {
+        for (c, tree) in row.iter().enumerate() {
             let (new_r, new_c) = match rotation {
                 Rotation::D0 => (r, c),
-                Rotation::D90 => (c, n - 1 - r),
-                Rotation::D180 => (n - 1 - r, n - 1 - c),
-                Rotation::D270 => (n - 1 - c, r),
+                Rotation::D90 => (c, rows - 1 - r),
+                Rotation::D180 => (rows - 1 - r, cols - 1 - c),
+                Rotation::D270 => (cols - 1 - c, r),
             };
-            result[new_r][new_c] = tree_grid[r][c].clone();
+            result[new_r][new_c] = tree.clone();
         }
     }
     result
 }
 
+type Grid<R> = Vec<Vec<R>>;
+
 fn perform_grid_op_4directional<T: Default + Clone, R: Default + Clone>(
-    tree_grid: &Vec<Vec<T>>,
-    f: &dyn Fn(&Vec<Vec<T>>) -> Vec<Vec<R>>,
-) -> (Vec<Vec<R>>, Vec<Vec<R>>, Vec<Vec<R>>, Vec<Vec<R>>) {
+    tree_grid: &[Vec<T>],
+    f: &dyn Fn(&[Vec<T>]) -> Grid<R>,
+) -> (Grid<R>, Grid<R>, Grid<R>, Grid<R>) {
     (
         rotate_tree_grid(&f(&rotate_tree_grid(tree_grid, Rotation::D0)), Rotation::D0),
         rotate_tree_grid(
@@ -63,96 +69,598 @@ fn perform_grid_op_4directional<T: Default + Clone, R: Default + Clone>(
     )
 }
 
-fn count_visible_trees(tree_grid: &Vec<Vec<u8>>) -> usize {
-    let n = tree_grid.len();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ViewDirections {
+    Axis,
+    AxisAndDiagonal,
+    // Rays in every direction (dr, dc) with |dr| and |dc| at most the given value, so the angles
+    // get finer as it grows. A ray is blocked by every tree whose cell it passes through.
+    LineOfSight(i32),
+}
 
-    let is_visible_from_left_to_right_map = |tree_grid: &Vec<Vec<u8>>| {
-        let mut result = vec![vec![false; n]; n];
-        for r in 0..n {
-            let mut max_height = -1;
-            for c in 0..n {
-                let height = tree_grid[r][c] as i32;
-                if height > max_height {
-                    max_height = height;
-                    result[r][c] = true;
-                }
+const DIAGONAL_STEPS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const DIAGONAL_NAMES: [&str; 4] = ["up-left", "up-right", "down-left", "down-right"];
+
+fn line_of_sight_steps(max_step: i32) -> Vec<(i32, i32)> {
+    (-max_step..=max_step)
+        .cartesian_product(-max_step..=max_step)
+        .filter(|&(dr, dc)| gcd(dr, dc) == 1)
+        .collect_vec()
+}
+
+// The cells a ray from the centre of `start` in direction `(dr, dc)` passes through, in order,
+// until it leaves the grid. A ray through a cell corner continues in the diagonal cell.
+fn ray_cells(
+    start: (i32, i32),
+    (dr, dc): (i32, i32),
+    rows: i32,
+    cols: i32,
+) -> impl Iterator<Item = (i32, i32)> {
+    // The ray crosses its k-th column boundary at t = (2k + 1) / (2 |dc|) and its k-th row
+    // boundary at t = (2k + 1) / (2 |dr|); both are scaled by 2 |dr| |dc| to compare them exactly.
+    let (adr, adc) = (dr.abs() as i64, dc.abs() as i64);
+    let (mut r, mut c) = start;
+    let (mut row_crossings, mut col_crossings) = (0, 0);
+    iter::from_fn(move || {
+        let next_row = (adr > 0).then(|| (2 * row_crossings + 1) * adc);
+        let next_col = (adc > 0).then(|| (2 * col_crossings + 1) * adr);
+        let (cross_row, cross_col) = match (next_row, next_col) {
+            (None, None) => return None,
+            (Some(_), None) => (true, false),
+            (None, Some(_)) => (false, true),
+            (Some(row_t), Some(col_t)) => (row_t <= col_t, col_t <= row_t),
+        };
+        if cross_row {
+            r += dr.signum();
+            row_crossings += 1;
+        }
+        if cross_col {
+            c += dc.signum();
+            col_crossings += 1;
+        }
+        (0 <= r && r < rows && 0 <= c && c < cols).then_some((r, c))
+    })
+}
+
+// Whether each tree can be seen from outside the grid looking back along `step`.
+fn visible_along_ray<H: Ord + Copy>(tree_grid: &[Vec<H>], step: (i32, i32)) -> Grid<bool> {
+    let rows = tree_grid.len() as i32;
+    let cols = tree_grid.first().map_or(0, |row| row.len()) as i32;
+    tree_grid
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(c, &height)| {
+                    ray_cells((r as i32, c as i32), step, rows, cols)
+                        .all(|(r, c)| tree_grid[r as usize][c as usize] < height)
+                })
+                .collect_vec()
+        })
+        .collect_vec()
+}
+
+fn visible_from_line_start<H: Ord + Copy>(heights: &[H]) -> Vec<bool> {
+    let mut max_height = None;
+    heights
+        .iter()
+        .map(|&height| {
+            let visible = max_height.is_none_or(|max_height| height > max_height);
+            max_height = max(max_height, Some(height));
+            visible
+        })
+        .collect()
+}
+
+// For every tree, the closest tree towards the line start that is at least as tall is found on a
+// stack of non-increasing heights, so each line takes linear time.
+fn view_ranges_to_line_start<H: Ord>(heights: &[H]) -> Vec<u32> {
+    let mut stack: Vec<usize> = Vec::new();
+    heights
+        .iter()
+        .enumerate()
+        .map(|(i, height)| {
+            while stack.last().is_some_and(|&j| heights[j] < *height) {
+                stack.pop();
             }
+            let view_range = i - stack.last().copied().unwrap_or(0);
+            stack.push(i);
+            view_range as u32
+        })
+        .collect()
+}
+
+fn perform_row_op<H, R>(tree_grid: &[Vec<H>], line_op: &dyn Fn(&[H]) -> Vec<R>) -> Grid<R> {
+    tree_grid.iter().map(|row| line_op(row)).collect()
+}
+
+// Applies `line_op` to every line of trees visited with the given step, starting at the grid edge.
+fn perform_line_op<H: Copy, R: Default + Clone>(
+    tree_grid: &[Vec<H>],
+    (dr, dc): (i32, i32),
+    line_op: &dyn Fn(&[H]) -> Vec<R>,
+) -> Grid<R> {
+    let rows = tree_grid.len() as i32;
+    let cols = tree_grid.first().map_or(0, |row| row.len()) as i32;
+    let inside = |(r, c): (i32, i32)| 0 <= r && r < rows && 0 <= c && c < cols;
+    let mut result = vec![vec![R::default(); cols as usize]; rows as usize];
+    for start in (0..rows).cartesian_product(0..cols) {
+        if inside((start.0 - dr, start.1 - dc)) {
+            continue;
         }
-        result
-    };
+        let cells = iter::successors(Some(start), |&(r, c)| Some((r + dr, c + dc)))
+            .take_while(|&cell| inside(cell))
+            .collect_vec();
+        let heights = cells
+            .iter()
+            .map(|&(r, c)| tree_grid[r as usize][c as usize])
+            .collect_vec();
+        for ((r, c), value) in cells.into_iter().zip(line_op(&heights)) {
+            result[r as usize][c as usize] = value;
+        }
+    }
+    result
+}
 
-    let (visibility_map_left, visibility_map_top, visibility_map_right, visibility_map_down) =
-        perform_grid_op_4directional(&tree_grid, &is_visible_from_left_to_right_map);
-
-    let mut visible_trees = 0;
-    for r in 0..n {
-        for c in 0..n {
-            let visible = visibility_map_left[r][c]
-                || visibility_map_right[r][c]
-                || visibility_map_top[r][c]
-                || visibility_map_down[r][c];
-            if visible {
-                visible_trees += 1;
+// The number of directions from which each tree can be seen from outside the grid.
+fn visibility_counts<H: Ord + Copy + Default>(
+    tree_grid: &[Vec<H>],
+    directions: ViewDirections,
+) -> Grid<u32> {
+    let mut maps = Vec::new();
+    match directions {
+        ViewDirections::Axis => {}
+        ViewDirections::AxisAndDiagonal => {
+            for step in DIAGONAL_STEPS {
+                maps.push(perform_line_op(tree_grid, step, &visible_from_line_start));
+            }
+        }
+        ViewDirections::LineOfSight(max_step) => {
+            for step in line_of_sight_steps(max_step) {
+                maps.push(visible_along_ray(tree_grid, step));
             }
         }
     }
+    if !matches!(directions, ViewDirections::LineOfSight(_)) {
+        let is_visible_from_left_to_right_map =
+            |tree_grid: &[Vec<H>]| perform_row_op(tree_grid, &visible_from_line_start);
+        let (left, top, right, down) =
+            perform_grid_op_4directional(tree_grid, &is_visible_from_left_to_right_map);
+        maps.extend([left, top, right, down]);
+    }
 
-    visible_trees
+    tree_grid
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            (0..row.len())
+                .map(|c| maps.iter().filter(|map| map[r][c]).count() as u32)
+                .collect()
+        })
+        .collect()
 }
 
-fn compute_scenic_score(tree_grid: &Vec<Vec<u8>>) -> Vec<Vec<u32>> {
-    let n = tree_grid.len();
+fn count_visible_trees<H: Ord + Copy + Default>(
+    tree_grid: &[Vec<H>],
+    directions: ViewDirections,
+) -> usize {
+    visibility_counts(tree_grid, directions)
+        .iter()
+        .flatten()
+        .filter(|&&count| count > 0)
+        .count()
+}
 
-    let view_range_from_left_to_right_map = |tree_grid: &Vec<Vec<u8>>| {
-        let mut result = vec![vec![0; n]; n];
-        for h in 0..=9 {
-            for r in 0..n {
-                let mut tiles_since = -1;
-                for c in 0..n {
-                    tiles_since += 1;
-                    let height = tree_grid[r][c] as i32;
-                    if height >= h {
-                        if height == h {
-                            result[r][c] = tiles_since as u32;
-                        }
-                        tiles_since = 0;
-                    }
-                }
-                result[r][n - 1] = max(result[r][n - 1], tiles_since as u32);
+fn compute_scenic_score<H: Ord + Copy + Default>(
+    tree_grid: &[Vec<H>],
+    include_diagonals: bool,
+) -> ScenicScores {
+    let view_range_from_left_to_right_map =
+        |tree_grid: &[Vec<H>]| perform_row_op(tree_grid, &view_ranges_to_line_start);
+
+    let (left, up, right, down) =
+        perform_grid_op_4directional(tree_grid, &view_range_from_left_to_right_map);
+    let mut view_ranges = vec![("left", left), ("up", up), ("right", right), ("down", down)];
+    if include_diagonals {
+        for (step, name) in DIAGONAL_STEPS.iter().zip(DIAGONAL_NAMES) {
+            view_ranges.push((
+                name,
+                perform_line_op(tree_grid, *step, &view_ranges_to_line_start),
+            ));
+        }
+    }
+    ScenicScores::new(view_ranges)
+}
+
+type Position = (usize, usize);
+
+struct ScenicScores {
+    scores: Grid<u64>,
+    view_ranges: Vec<(&'static str, Grid<u32>)>,
+}
+
+impl ScenicScores {
+    fn new(view_ranges: Vec<(&'static str, Grid<u32>)>) -> ScenicScores {
+        let (_, first) = view_ranges.first().unwrap();
+        let scores = (0..first.len())
+            .map(|r| {
+                (0..first[r].len())
+                    .map(|c| {
+                        view_ranges
+                            .iter()
+                            .map(|(_, ranges)| ranges[r][c] as u64)
+                            .product()
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        ScenicScores {
+            scores,
+            view_ranges,
+        }
+    }
+
+    fn max(&self) -> Option<u64> {
+        self.scores.iter().flatten().max().copied()
+    }
+
+    fn top_k(&self, k: usize) -> Vec<(Position, u64)> {
+        self.scores
+            .iter()
+            .enumerate()
+            .flat_map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(c, &score)| ((r, c), score))
+            })
+            .sorted_by_key(|&(position, score)| (Reverse(score), position))
+            .take(k)
+            .collect_vec()
+    }
+
+    fn breakdown(&self, (r, c): Position) -> Vec<(&'static str, u32)> {
+        self.view_ranges
+            .iter()
+            .map(|(name, ranges)| (*name, ranges[r][c]))
+            .collect_vec()
+    }
+}
+
+// Answers best-score queries over rectangles of a fixed set of scores.
+struct RectangleIndex {
+    rows: usize,
+    cols: usize,
+    // sparse_table[i][j][r][c]: best score in the 2^i x 2^j rectangle starting at (r, c)
+    sparse_table: Vec<Vec<Grid<(u64, Position)>>>,
+}
+
+impl RectangleIndex {
+    fn new(scores: &ScenicScores) -> RectangleIndex {
+        let rows = scores.scores.len();
+        let cols = scores.scores.first().map_or(0, |row| row.len());
+        let mut sparse_table = vec![vec![scores
+            .scores
+            .iter()
+            .enumerate()
+            .map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(c, &score)| (score, (r, c)))
+                    .collect_vec()
+            })
+            .collect_vec()]];
+        for i in 0..=rows.max(1).ilog2() as usize {
+            if i > 0 {
+                let previous = &sparse_table[i - 1][0];
+                let half = 1 << (i - 1);
+                let level = (0..=rows - (1 << i))
+                    .map(|r| {
+                        (0..cols)
+                            .map(|c| max(previous[r][c], previous[r + half][c]))
+                            .collect_vec()
+                    })
+                    .collect_vec();
+                sparse_table.push(vec![level]);
+            }
+            for j in 1..=cols.max(1).ilog2() as usize {
+                let previous = &sparse_table[i][j - 1];
+                let half = 1 << (j - 1);
+                let level = previous
+                    .iter()
+                    .map(|row| {
+                        (0..=cols - (1 << j))
+                            .map(|c| max(row[c], row[c + half]))
+                            .collect_vec()
+                    })
+                    .collect_vec();
+                sparse_table[i].push(level);
             }
         }
-        result
-    };
 
-    let (view_range_left, view_range_top, view_range_right, view_range_down) =
-        perform_grid_op_4directional(tree_grid, &view_range_from_left_to_right_map);
+        RectangleIndex {
+            rows,
+            cols,
+            sparse_table,
+        }
+    }
 
-    let mut scenic_score = vec![vec![0; n]; n];
-    for r in 0..n {
-        for c in 0..n {
-            let score = view_range_left[r][c]
-                * view_range_top[r][c]
-                * view_range_right[r][c]
-                * view_range_down[r][c];
-            scenic_score[r][c] = score;
+    // Answered in constant time from four overlapping power-of-two rectangles.
+    fn best_in_rectangle(&self, rows: Range<usize>, cols: Range<usize>) -> Option<(Position, u64)> {
+        if rows.is_empty() || cols.is_empty() || rows.end > self.rows || cols.end > self.cols {
+            return None;
         }
+        let i = rows.len().ilog2() as usize;
+        let j = cols.len().ilog2() as usize;
+        let table = &self.sparse_table[i][j];
+        let (last_r, last_c) = (rows.end - (1 << i), cols.end - (1 << j));
+        let (score, position) = [
+            table[rows.start][cols.start],
+            table[rows.start][last_c],
+            table[last_r][cols.start],
+            table[last_r][last_c],
+        ]
+        .into_iter()
+        .max()
+        .unwrap();
+        Some((position, score))
     }
+}
 
-    scenic_score
+fn heat_map<T: Copy + Into<u64>>(values: &[Vec<T>]) -> String {
+    const SHADES: &[u8] = b" .:-=+*#%@";
+    let max_value = values
+        .iter()
+        .flatten()
+        .map(|&value| value.into())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    values
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&value| {
+                    let shade = value.into() * (SHADES.len() as u64 - 1) / max_value;
+                    SHADES[shade as usize] as char
+                })
+                .collect::<String>()
+        })
+        .join("\n")
 }
 
 #[allow(dead_code)]
 pub fn day_8() {
     let tree_grid = read_input();
-    assert_eq!(tree_grid.len(), tree_grid.first().unwrap().len());
     println!("{:?}", tree_grid);
 
     // star 1
-    println!("{:?}", count_visible_trees(&tree_grid));
+    println!(
+        "{:?}",
+        count_visible_trees(&tree_grid, ViewDirections::Axis)
+    );
 
     // star 2
+    println!("{:?}", compute_scenic_score(&tree_grid, false).max());
+
+    // diagonal and line-of-sight views
     println!(
-        "{:?}",
-        compute_scenic_score(&tree_grid).iter().flatten().max()
+        "visible with diagonals: {}, with line of sight: {}",
+        count_visible_trees(&tree_grid, ViewDirections::AxisAndDiagonal),
+        count_visible_trees(&tree_grid, ViewDirections::LineOfSight(5))
+    );
+    let diagonal_scenic_score = compute_scenic_score(&tree_grid, true);
+    println!(
+        "best scenic score with diagonals: {:?}",
+        diagonal_scenic_score.max()
+    );
+    println!(
+        "{}",
+        heat_map(&visibility_counts(
+            &tree_grid,
+            ViewDirections::LineOfSight(5)
+        ))
     );
+    println!("{}", heat_map(&diagonal_scenic_score.scores));
+
+    // tree-house placement queries
+    let scenic_scores = compute_scenic_score(&tree_grid, false);
+    let top_positions = scenic_scores.top_k(3);
+    println!("top positions: {:?}", top_positions);
+    println!(
+        "best tree breakdown: {:?}",
+        scenic_scores.breakdown(top_positions[0].0)
+    );
+    let rectangle_index = RectangleIndex::new(&scenic_scores);
+    let rows = scenic_scores.scores.len();
+    let cols = scenic_scores.scores.first().map_or(0, |row| row.len());
+    for (rows, cols) in [
+        (rows / 10..rows * 4 / 10, cols / 5..cols / 4),
+        (0..rows, cols / 2..cols / 2 + 1),
+        (rows / 3..rows / 3 + 1, 0..cols * 7 / 9),
+    ] {
+        match rectangle_index.best_in_rectangle(rows.clone(), cols.clone()) {
+            Some(best) => println!("best in {:?} x {:?}: {:?}", rows, cols, best),
+            None => println!("no trees in {:?} x {:?}", rows, cols),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::iproduct;
+
+    const EXAMPLE: &str = "30373
+25512
+65332";
+
+    fn transpose<T: Copy>(tree_grid: &[Vec<T>]) -> Grid<T> {
+        (0..tree_grid[0].len())
+            .map(|c| tree_grid.iter().map(|row| row[c]).collect_vec())
+            .collect_vec()
+    }
+
+    #[test]
+    fn rotations_handle_non_square_grids() {
+        let example = parse_grid(EXAMPLE);
+        let rotated = rotate_tree_grid(&example, Rotation::D90);
+        assert_eq!(rotated.len(), 5);
+        assert_eq!(rotated[0], [6, 2, 3]);
+        let mut back = example.clone();
+        for _ in 0..4 {
+            back = rotate_tree_grid(&back, Rotation::D90);
+        }
+        assert_eq!(back, example);
+        assert_eq!(transpose(&example)[4], [3, 2, 2]);
+    }
+
+    #[test]
+    fn example_forest() {
+        let example = parse_grid(EXAMPLE);
+        assert_eq!(count_visible_trees(&example, ViewDirections::Axis), 14);
+        assert_eq!(compute_scenic_score(&example, false).max(), Some(2));
+    }
+
+    #[test]
+    fn transposed_strip_gives_the_same_answers() {
+        let strip = read_input()[..20].to_vec();
+        let transposed = transpose(&strip);
+        assert_eq!(
+            count_visible_trees(&strip, ViewDirections::Axis),
+            count_visible_trees(&transposed, ViewDirections::Axis)
+        );
+        assert_eq!(
+            compute_scenic_score(&strip, false).scores,
+            transpose(&compute_scenic_score(&transposed, false).scores)
+        );
+    }
+
+    // The original implementation, limited to heights 0 to 9, kept to check `compute_scenic_score`.
+    fn compute_scenic_score_reference(tree_grid: &[Vec<u8>]) -> ScenicScores {
+        let view_range_from_left_to_right_map = |tree_grid: &[Vec<u8>]| {
+            let mut result = tree_grid
+                .iter()
+                .map(|row| vec![0; row.len()])
+                .collect::<Vec<_>>();
+            for h in 0..=9 {
+                for (r, row) in tree_grid.iter().enumerate() {
+                    let mut tiles_since = -1;
+                    for (c, &height) in row.iter().enumerate() {
+                        tiles_since += 1;
+                        let height = height as i32;
+                        if height >= h {
+                            if height == h {
+                                result[r][c] = tiles_since as u32;
+                            }
+                            tiles_since = 0;
+                        }
+                    }
+                    let last = row.len() - 1;
+                    result[r][last] = max(result[r][last], tiles_since as u32);
+                }
+            }
+            result
+        };
+
+        let (left, up, right, down) =
+            perform_grid_op_4directional(tree_grid, &view_range_from_left_to_right_map);
+        ScenicScores::new(vec![
+            ("left", left),
+            ("up", up),
+            ("right", right),
+            ("down", down),
+        ])
+    }
+
+    #[test]
+    fn linear_scenic_scores_match_reference() {
+        for tree_grid in [parse_grid(EXAMPLE), read_input()] {
+            assert_eq!(
+                compute_scenic_score(&tree_grid, false).scores,
+                compute_scenic_score_reference(&tree_grid).scores
+            );
+        }
+    }
+
+    #[test]
+    fn scenic_scores_work_for_arbitrary_heights() {
+        let tree_grid = read_input();
+        let scaled_grid = tree_grid
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&h| h as i64 * 1000 - 4500)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            compute_scenic_score(&scaled_grid, false).scores,
+            compute_scenic_score(&tree_grid, false).scores
+        );
+    }
+
+    #[test]
+    fn rays_pass_through_every_cell_they_cross() {
+        assert_eq!(
+            ray_cells((2, 0), (-1, 2), 3, 5).collect_vec(),
+            [(2, 1), (1, 1), (1, 2), (1, 3), (0, 3), (0, 4)]
+        );
+        assert_eq!(
+            ray_cells((0, 0), (1, 1), 3, 5).collect_vec(),
+            [(1, 1), (2, 2)]
+        );
+        assert_eq!(ray_cells((1, 4), (0, 1), 3, 5).count(), 0);
+
+        let mut tree_grid = vec![vec![0; 5]; 3];
+        tree_grid[2][0] = 5;
+        assert!(visible_along_ray(&tree_grid, (-1, 2))[2][0]);
+        tree_grid[1][1] = 7;
+        assert!(!visible_along_ray(&tree_grid, (-1, 2))[2][0]);
+    }
+
+    #[test]
+    fn line_of_sight_with_unit_steps_is_axis_and_diagonal() {
+        for tree_grid in [parse_grid(EXAMPLE), read_input()] {
+            assert_eq!(
+                visibility_counts(&tree_grid, ViewDirections::LineOfSight(1)),
+                visibility_counts(&tree_grid, ViewDirections::AxisAndDiagonal)
+            );
+        }
+    }
+
+    #[test]
+    fn rectangle_queries_match_brute_force() {
+        let scenic_scores = compute_scenic_score(&read_input(), false);
+        let rectangle_index = RectangleIndex::new(&scenic_scores);
+        let rows = scenic_scores.scores.len();
+        let cols = scenic_scores.scores[0].len();
+        for (r0, r1, c0, c1) in iproduct!(
+            (0..rows).step_by(13),
+            (1..=rows).step_by(17),
+            (0..cols).step_by(11),
+            (1..=cols).step_by(19)
+        ) {
+            let best = rectangle_index.best_in_rectangle(r0..r1, c0..c1);
+            if r0 >= r1 || c0 >= c1 {
+                assert_eq!(best, None);
+                continue;
+            }
+            let brute_force = scenic_scores.scores[r0..r1]
+                .iter()
+                .flat_map(|row| row[c0..c1].iter())
+                .max()
+                .copied();
+            let ((r, c), score) = best.unwrap();
+            assert_eq!(Some(score), brute_force);
+            assert!((r0..r1).contains(&r) && (c0..c1).contains(&c));
+            assert_eq!(scenic_scores.scores[r][c], score);
+        }
+        assert_eq!(rectangle_index.best_in_rectangle(0..rows + 1, 0..1), None);
+        assert_eq!(
+            rectangle_index.best_in_rectangle(0..1, cols..cols + 1),
+            None
+        );
+    }
 }
@@ -1,104 +1,285 @@
 use itertools::Itertools;
+use num::signum;
 use std::collections::HashSet;
-use std::{fs, iter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+use std::{array, fs, io, iter, thread};
 
-#[derive(Debug, Clone)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+type Position<const D: usize> = [i32; D];
+
+fn add<const D: usize>(a: Position<D>, b: Position<D>) -> Position<D> {
+    array::from_fn(|i| a[i] + b[i])
 }
 
-impl Direction {
-    fn from_string(string: &str) -> Option<Direction> {
-        let c = string.chars().exactly_one().ok()?;
-        match c {
-            'D' => Some(Direction::Down),
-            'U' => Some(Direction::Up),
-            'L' => Some(Direction::Left),
-            'R' => Some(Direction::Right),
-            _ => None,
-        }
+fn scale<const D: usize>(a: Position<D>, factor: i32) -> Position<D> {
+    a.map(|x| x * factor)
+}
+
+// Each letter is a unit step along one axis: L/R along x, D/U along y and B/F along z, so `UR` is
+// a diagonal and `URF` a space diagonal.
+fn direction_vector<const D: usize>(string: &str) -> Option<Position<D>> {
+    let mut vector = [0; D];
+    for c in string.chars() {
+        let (axis, sign) = match c {
+            'L' => (0, -1),
+            'R' => (0, 1),
+            'D' => (1, -1),
+            'U' => (1, 1),
+            'B' => (2, -1),
+            'F' => (2, 1),
+            _ => return None,
+        };
+        *vector.get_mut(axis)? = sign;
     }
+    Some(vector)
 }
 
-fn read_input() -> Vec<Direction> {
-    let file_contents = fs::read_to_string("day9_puzzle.txt").expect("Unable to read file");
+// A move is either a direction and a step count, such as `UR 4`, or an arbitrary vector for the
+// head, such as `V 3 -2`.
+fn parse_moves<const D: usize>(file_contents: &str) -> Vec<Position<D>> {
     file_contents
-        .split("\n")
-        .flat_map(|line| {
-            let (a, b) = line.split_once(" ").unwrap();
-            iter::repeat(Direction::from_string(a).unwrap()).take(b.parse().unwrap())
+        .split('\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (a, b) = line.split_once(' ').unwrap();
+            if a == "V" {
+                let coordinates = b.split(' ').map(|x| x.parse().unwrap()).collect_vec();
+                coordinates.try_into().unwrap()
+            } else {
+                let steps = b.parse::<i32>().unwrap();
+                scale(direction_vector(a).unwrap(), steps)
+            }
         })
         .collect_vec()
 }
 
+fn read_input() -> Vec<Position<2>> {
+    let file_contents = fs::read_to_string("day9_puzzle.txt").expect("Unable to read file");
+    parse_moves(&file_contents)
+}
+
+// The unit steps the head takes for a move: diagonally while several coordinates differ, then
+// along the remaining axes.
+fn unit_steps<const D: usize>(vector: Position<D>) -> impl Iterator<Item = Position<D>> {
+    iter::successors(Some(vector), |remaining| {
+        Some(add(*remaining, remaining.map(|x| -signum(x))))
+    })
+    .take_while(|&remaining| remaining != [0; D])
+    .map(|remaining| remaining.map(signum))
+}
+
+// How a knot follows the one ahead of it. Every rule only looks at relative positions, which
+// `apply_move` relies on to batch rigid translations of the rope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FollowRule {
+    // One step along each axis towards the leader once it is more than one step away.
+    Classic,
+    // Each knot takes the position its leader had before the step.
+    Snake,
+    // Like `Classic`, but the leader may be up to `k` steps away along every axis.
+    Elastic(i32),
+}
+
+impl FollowRule {
+    fn follow<const D: usize>(
+        &self,
+        leader_before: Position<D>,
+        leader: Position<D>,
+        follower: Position<D>,
+    ) -> Position<D> {
+        let max_slack = match self {
+            FollowRule::Classic => 1,
+            FollowRule::Snake => return leader_before,
+            FollowRule::Elastic(k) => *k,
+        };
+        let delta: Position<D> = array::from_fn(|i| leader[i] - follower[i]);
+        if delta.iter().any(|d| d.abs() > max_slack) {
+            add(follower, delta.map(signum))
+        } else {
+            follower
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-struct RopeState {
-    knots: Vec<(i32, i32)>,
-    visited_tail_positions: HashSet<(i32, i32)>,
+struct KnotStats<const D: usize> {
+    visited_positions: HashSet<Position<D>>,
+    distance_travelled: f64,
+    bounding_box: (Position<D>, Position<D>),
+    first_moved_at_step: Option<usize>,
 }
 
-impl RopeState {
-    fn new(knot_count: usize) -> RopeState {
+impl<const D: usize> KnotStats<D> {
+    fn new() -> KnotStats<D> {
+        KnotStats {
+            visited_positions: HashSet::from([[0; D]]),
+            distance_travelled: 0.0,
+            bounding_box: ([0; D], [0; D]),
+            first_moved_at_step: None,
+        }
+    }
+
+    fn record_move(&mut self, from: Position<D>, to: Position<D>, step: usize) {
+        if from == to {
+            return;
+        }
+        self.visited_positions.insert(to);
+        self.distance_travelled += (0..D)
+            .map(|i| ((to[i] - from[i]) as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let (min, max) = &mut self.bounding_box;
+        *min = array::from_fn(|i| min[i].min(to[i]));
+        *max = array::from_fn(|i| max[i].max(to[i]));
+        self.first_moved_at_step.get_or_insert(step);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RopeReport<const D: usize> {
+    tail_visits: usize,
+    steps: usize,
+    knot_stats: Option<Vec<KnotStats<D>>>,
+}
+
+#[derive(Debug, Clone)]
+struct RopeState<const D: usize = 2> {
+    knots: Vec<Position<D>>,
+    visited_tail_positions: HashSet<Position<D>>,
+    steps: usize,
+    knot_stats: Option<Vec<KnotStats<D>>>,
+    follow_rule: FollowRule,
+    trace: Option<RopeTrace<D>>,
+}
+
+impl<const D: usize> RopeState<D> {
+    fn new(knot_count: usize, track_all_knots: bool, follow_rule: FollowRule) -> RopeState<D> {
         RopeState {
-            knots: vec![(0, 0); knot_count],
-            visited_tail_positions: HashSet::from([(0, 0)]),
+            follow_rule,
+            knots: vec![[0; D]; knot_count],
+            visited_tail_positions: HashSet::from([[0; D]]),
+            steps: 0,
+            knot_stats: track_all_knots.then(|| vec![KnotStats::new(); knot_count]),
+            trace: None,
         }
     }
 
-    fn apply_move(&mut self, direction: &Direction) {
-        // Move head.
-        {
-            let head = self.knots.first_mut().unwrap();
-            match direction {
-                Direction::Up => head.1 += 1,
-                Direction::Down => head.1 -= 1,
-                Direction::Left => head.0 -= 1,
-                Direction::Right => head.0 += 1,
+    fn with_trace(mut self) -> RopeState<D> {
+        self.trace = Some(RopeTrace {
+            frames: vec![self.knots.clone()],
+        });
+        self
+    }
+
+    fn report(self) -> RopeReport<D> {
+        RopeReport {
+            tail_visits: self.visited_tail_positions.len(),
+            steps: self.steps,
+            knot_stats: self.knot_stats,
+        }
+    }
+
+    // Moves the head along `vector`. Once a step has moved every knot by that same step, the rope
+    // is stretched and the remaining steps in that direction translate it as a whole.
+    fn apply_move(&mut self, vector: Position<D>) {
+        let mut steps = unit_steps(vector).dedup_with_count().collect_vec();
+        steps.reverse();
+        while let Some((count, step)) = steps.pop() {
+            let knots_before = self.knots.clone();
+            self.apply_step(step);
+            let stretched = knots_before
+                .iter()
+                .zip(self.knots.iter())
+                .all(|(before, after)| add(*before, step) == *after);
+            if stretched {
+                let tail = *self.knots.last().unwrap();
+                for i in 1..count as i32 {
+                    self.visited_tail_positions
+                        .insert(add(tail, scale(step, i)));
+                }
+                if let Some(knot_stats) = self.knot_stats.as_mut() {
+                    for (knot, stats) in self.knots.iter().zip(knot_stats.iter_mut()) {
+                        for i in 1..count as i32 {
+                            let from = add(*knot, scale(step, i - 1));
+                            let to = add(*knot, scale(step, i));
+                            stats.record_move(from, to, self.steps + i as usize);
+                        }
+                    }
+                }
+                if let Some(trace) = self.trace.as_mut() {
+                    for i in 1..count as i32 {
+                        let shift = scale(step, i);
+                        trace
+                            .frames
+                            .push(self.knots.iter().map(|knot| add(*knot, shift)).collect());
+                    }
+                }
+                self.steps += count - 1;
+                let shift = scale(step, count as i32 - 1);
+                for knot in self.knots.iter_mut() {
+                    *knot = add(*knot, shift);
+                }
+            } else if count > 1 {
+                steps.push((count - 1, step));
             }
         }
+    }
+
+    fn apply_step(&mut self, step: Position<D>) {
+        let knots_before = self.knot_stats.as_ref().map(|_| self.knots.clone());
+        self.steps += 1;
+
+        // Move head.
+        let mut leader_before = {
+            let head = self.knots.first_mut().unwrap();
+            let before = *head;
+            *head = add(*head, step);
+            before
+        };
 
         // Move following knots.
         {
             for leading_i in 0..self.knots.len() - 1 {
                 let leading = self.knots[leading_i];
                 let following = self.knots.get_mut(leading_i + 1).unwrap();
-                let delta = (leading.0 - following.0, leading.1 - following.1);
-                if delta.0 >= 2 {
-                    following.0 += 1;
-                    following.1 += num::signum(delta.1);
-                } else if delta.0 <= -2 {
-                    following.0 -= 1;
-                    following.1 += num::signum(delta.1);
-                } else if delta.1 >= 2 {
-                    following.1 += 1;
-                    following.0 += num::signum(delta.0);
-                } else if delta.1 <= -2 {
-                    following.1 -= 1;
-                    following.0 += num::signum(delta.0);
-                }
+                let following_before = *following;
+                *following = self.follow_rule.follow(leader_before, leading, *following);
+                leader_before = following_before;
             }
         }
 
         // Record tail position.
         self.visited_tail_positions
             .insert(*self.knots.last().unwrap());
+
+        // Record every knot's movement.
+        if let (Some(knot_stats), Some(knots_before)) = (self.knot_stats.as_mut(), knots_before) {
+            for ((stats, before), after) in knot_stats.iter_mut().zip(knots_before).zip(&self.knots)
+            {
+                stats.record_move(before, *after, self.steps);
+            }
+        }
+
+        // Record trace.
+        if let Some(trace) = self.trace.as_mut() {
+            trace.frames.push(self.knots.clone());
+        }
     }
+}
 
-    #[allow(dead_code)]
+impl RopeState {
     fn pretty_print(&self) {
         let x_positions = self
             .visited_tail_positions
             .iter()
-            .map(|p| p.0)
-            .chain(self.knots.iter().map(|knot| knot.0));
+            .map(|p| p[0])
+            .chain(self.knots.iter().map(|knot| knot[0]));
         let y_positions = self
             .visited_tail_positions
             .iter()
-            .map(|p| p.1)
-            .chain(self.knots.iter().map(|knot| knot.1));
+            .map(|p| p[1])
+            .chain(self.knots.iter().map(|knot| knot[1]));
         let min_x = x_positions.clone().min().unwrap();
         let max_x = x_positions.clone().max().unwrap();
         let min_y = y_positions.clone().min().unwrap();
@@ -106,13 +287,13 @@ impl RopeState {
         for y in (min_y..=max_y).rev() {
             for x in min_x..=max_x {
                 let c = if let Some((knot_i, _)) =
-                    self.knots.iter().find_position(|knot| **knot == (x, y))
+                    self.knots.iter().find_position(|knot| **knot == [x, y])
                 {
                     knot_i.to_string()
                 } else {
-                    if (0, 0) == (x, y) {
+                    if [0, 0] == [x, y] {
                         "s"
-                    } else if self.visited_tail_positions.contains(&(x, y)) {
+                    } else if self.visited_tail_positions.contains(&[x, y]) {
                         "#"
                     } else {
                         "."
@@ -126,20 +307,380 @@ impl RopeState {
     }
 }
 
-fn solve_with_knots(knot_count: usize, move_list: &Vec<Direction>) -> usize {
-    let mut state = RopeState::new(knot_count);
+// The knot positions after every step of a simulation, starting from the initial state. Stored as
+// one line per step, with the knots' coordinates separated by spaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RopeTrace<const D: usize> {
+    frames: Vec<Vec<Position<D>>>,
+}
+
+impl<const D: usize> RopeTrace<D> {
+    fn from_text(text: &str) -> RopeTrace<D> {
+        let frames = text
+            .split('\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.split(' ')
+                    .map(|knot| {
+                        let coordinates = knot
+                            .split(',')
+                            .map(|x| x.parse::<i32>().unwrap())
+                            .collect_vec();
+                        coordinates.try_into().unwrap()
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        RopeTrace { frames }
+    }
+
+    fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for frame in &self.frames {
+            let line = frame.iter().map(|knot| knot.iter().join(",")).join(" ");
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    // Calls `f` with the simulation after every step in turn, adding each frame's tail to the
+    // visited positions as it goes rather than rebuilding them for every step like `state_at`.
+    fn for_each_state(&self, mut f: impl FnMut(&RopeState<D>)) {
+        let mut state = RopeState {
+            knots: Vec::new(),
+            visited_tail_positions: HashSet::new(),
+            steps: 0,
+            knot_stats: None,
+            follow_rule: FollowRule::Classic,
+            trace: None,
+        };
+        for (step, frame) in self.frames.iter().enumerate() {
+            state.knots = frame.clone();
+            state.steps = step;
+            state.visited_tail_positions.insert(*frame.last().unwrap());
+            f(&state);
+        }
+    }
+
+    // Rebuilds the simulation as it was after `step` steps.
+    fn state_at(&self, step: usize) -> RopeState<D> {
+        let frames = &self.frames[..=step];
+        RopeState {
+            knots: frames[step].clone(),
+            visited_tail_positions: frames.iter().map(|frame| *frame.last().unwrap()).collect(),
+            steps: step,
+            knot_stats: None,
+            follow_rule: FollowRule::Classic,
+            trace: None,
+        }
+    }
+}
+
+impl RopeTrace<2> {
+    // Renders the given step, or animates every step in place.
+    fn replay(&self, step: Option<usize>) {
+        match step {
+            Some(step) if step < self.frames.len() => self.state_at(step).pretty_print(),
+            Some(step) => println!(
+                "no step {}, the trace has {} steps",
+                step,
+                self.frames.len().saturating_sub(1)
+            ),
+            None if self.frames.is_empty() => println!("the trace is empty"),
+            None => self.for_each_state(|state| {
+                print!("\x1B[2J\x1B[H");
+                println!("step {} of {}", state.steps, self.frames.len() - 1);
+                state.pretty_print();
+                thread::sleep(Duration::from_millis(50));
+            }),
+        }
+    }
+}
+
+fn solve_with_knots<const D: usize>(
+    knot_count: usize,
+    move_list: &[Position<D>],
+    track_all_knots: bool,
+    follow_rule: FollowRule,
+) -> RopeReport<D> {
+    let mut state = RopeState::new(knot_count, track_all_knots, follow_rule);
 
     for mov in move_list {
-        state.apply_move(&mov);
+        state.apply_move(*mov);
     }
 
-    state.visited_tail_positions.len()
+    state.report()
 }
 
+const DIAGONAL_EXAMPLE: &str = "UR 7
+R 12
+DL 5
+V 3 -9
+U 20
+V -17 4
+DR 9
+L 3";
+
+const SPACE_EXAMPLE: &str = "F 5
+UR 4
+URF 6
+B 9
+V 2 -7 3
+DL 3
+L 8
+V -4 4 -4";
+
 #[allow(dead_code)]
 pub fn day_9() {
     let move_list = read_input();
 
-    println!("star 1 : {}", solve_with_knots(2, &move_list));
-    println!("star 2 : {}", solve_with_knots(10, &move_list));
+    // `record <file>` writes a trace of the 10-knot rope, `replay <file> [step]` shows it
+    let args = std::env::args().collect_vec();
+    match args.get(1).map(String::as_str) {
+        Some("record") => {
+            let Some(path) = args.get(2) else {
+                println!("usage: record <file>");
+                return;
+            };
+            let mut state = RopeState::new(10, false, FollowRule::Classic).with_trace();
+            for mov in &move_list {
+                state.apply_move(*mov);
+            }
+            let file = File::create(path).expect("Unable to create file");
+            state.trace.unwrap().write(BufWriter::new(file)).unwrap();
+            return;
+        }
+        Some("replay") => {
+            let Some(path) = args.get(2) else {
+                println!("usage: replay <file> [step]");
+                return;
+            };
+            let step = match args.get(3).map(|step| step.parse()) {
+                Some(Ok(step)) => Some(step),
+                Some(Err(_)) => {
+                    println!("not a step: {}", args[3]);
+                    return;
+                }
+                None => None,
+            };
+            let text = fs::read_to_string(path).expect("Unable to read file");
+            RopeTrace::<2>::from_text(&text).replay(step);
+            return;
+        }
+        _ => {}
+    }
+
+    println!(
+        "star 1 : {}",
+        solve_with_knots(2, &move_list, false, FollowRule::Classic).tail_visits
+    );
+    let report = solve_with_knots(10, &move_list, true, FollowRule::Classic);
+    println!("star 2 : {}", report.tail_visits);
+    for (i, stats) in report.knot_stats.unwrap().iter().enumerate() {
+        println!(
+            "knot {}: {} positions, travelled {:.1}, bounding box {:?}, first moved at step {:?} of {}",
+            i,
+            stats.visited_positions.len(),
+            stats.distance_travelled,
+            stats.bounding_box,
+            stats.first_moved_at_step,
+            report.steps
+        );
+    }
+
+    let diagonal_moves = parse_moves::<2>(DIAGONAL_EXAMPLE);
+    println!(
+        "diagonal example: {}",
+        solve_with_knots(10, &diagonal_moves, false, FollowRule::Classic).tail_visits
+    );
+
+    // Tail coverage under each physics model
+    for follow_rule in [
+        FollowRule::Classic,
+        FollowRule::Snake,
+        FollowRule::Elastic(1),
+        FollowRule::Elastic(3),
+    ] {
+        println!(
+            "{:?}: {} tail positions",
+            follow_rule,
+            solve_with_knots(10, &move_list, false, follow_rule).tail_visits
+        );
+    }
+
+    let space_moves = parse_moves::<3>(SPACE_EXAMPLE);
+    let space_report = solve_with_knots(10, &space_moves, false, FollowRule::Classic);
+    println!("space example: {}", space_report.tail_visits);
+
+    // Replay the diagonal example part way through
+    let mut state = RopeState::new(10, false, FollowRule::Classic).with_trace();
+    for mov in &diagonal_moves {
+        state.apply_move(*mov);
+    }
+    let trace = state.trace.unwrap();
+    println!("diagonal example after 40 steps:");
+    trace.replay(Some(40));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::iproduct;
+
+    fn solve_with_knots_stepwise<const D: usize>(
+        knot_count: usize,
+        move_list: &[Position<D>],
+        track_all_knots: bool,
+        follow_rule: FollowRule,
+    ) -> RopeReport<D> {
+        let mut state = RopeState::new(knot_count, track_all_knots, follow_rule);
+
+        for step in move_list.iter().flat_map(|mov| unit_steps(*mov)) {
+            state.apply_step(step);
+        }
+
+        state.report()
+    }
+
+    #[test]
+    fn batched_moves_match_single_steps() {
+        let move_list = read_input();
+        let diagonal_moves = parse_moves::<2>(DIAGONAL_EXAMPLE);
+        let follow_rules = [
+            FollowRule::Classic,
+            FollowRule::Snake,
+            FollowRule::Elastic(1),
+            FollowRule::Elastic(3),
+        ];
+        for (moves, knot_count, follow_rule) in
+            iproduct!([&move_list, &diagonal_moves], [2, 10], follow_rules)
+        {
+            let batched = solve_with_knots(knot_count, moves, true, follow_rule);
+            let stepwise = solve_with_knots_stepwise(knot_count, moves, true, follow_rule);
+            assert_eq!(batched.tail_visits, stepwise.tail_visits);
+            assert_eq!(batched.steps, stepwise.steps);
+            for (a, b) in batched
+                .knot_stats
+                .unwrap()
+                .iter()
+                .zip(stepwise.knot_stats.unwrap().iter())
+            {
+                assert_eq!(a.visited_positions, b.visited_positions);
+                assert_eq!(a.bounding_box, b.bounding_box);
+                assert_eq!(a.first_moved_at_step, b.first_moved_at_step);
+                assert!((a.distance_travelled - b.distance_travelled).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn trace_round_trips_and_replays() {
+        let mut state = RopeState::new(10, false, FollowRule::Classic).with_trace();
+        for mov in &parse_moves::<2>(DIAGONAL_EXAMPLE) {
+            state.apply_move(*mov);
+        }
+        let trace = state.trace.take().unwrap();
+        let mut text = Vec::new();
+        trace.write(&mut text).unwrap();
+        let parsed = RopeTrace::<2>::from_text(&String::from_utf8(text).unwrap());
+        assert_eq!(parsed, trace);
+        assert_eq!(trace.frames.len(), state.steps + 1);
+        let replayed = trace.state_at(state.steps);
+        assert_eq!(replayed.knots, state.knots);
+        assert_eq!(
+            replayed.visited_tail_positions,
+            state.visited_tail_positions
+        );
+    }
+
+    #[test]
+    fn batched_trace_matches_single_steps() {
+        let move_list = read_input();
+        let stepwise_trace = move_list.iter().flat_map(|mov| unit_steps(*mov)).scan(
+            RopeState::new(10, false, FollowRule::Classic),
+            |state, step| {
+                state.apply_step(step);
+                Some(state.knots.clone())
+            },
+        );
+        let mut state = RopeState::new(10, false, FollowRule::Classic).with_trace();
+        for mov in &move_list {
+            state.apply_move(*mov);
+        }
+        assert!(state.trace.unwrap().frames[1..]
+            .iter()
+            .cloned()
+            .eq(stepwise_trace));
+    }
+
+    #[test]
+    fn elastic_one_matches_classic() {
+        let move_list = read_input();
+        assert_eq!(
+            solve_with_knots(10, &move_list, false, FollowRule::Elastic(1)).tail_visits,
+            solve_with_knots(10, &move_list, false, FollowRule::Classic).tail_visits
+        );
+    }
+
+    #[test]
+    fn planar_3d_rope_matches_2d_rope() {
+        let move_list = read_input();
+        let planar_moves = move_list.iter().map(|[x, y]| [*x, 0, *y]).collect_vec();
+        assert_eq!(
+            solve_with_knots(10, &planar_moves, false, FollowRule::Classic).tail_visits,
+            solve_with_knots(10, &move_list, false, FollowRule::Classic).tail_visits
+        );
+    }
+
+    #[test]
+    fn space_example_batched_matches_single_steps() {
+        let space_moves = parse_moves::<3>(SPACE_EXAMPLE);
+        assert_eq!(
+            solve_with_knots(10, &space_moves, false, FollowRule::Classic).tail_visits,
+            solve_with_knots_stepwise(10, &space_moves, false, FollowRule::Classic).tail_visits
+        );
+    }
+
+    #[test]
+    fn knot_stats_for_example() {
+        let moves = parse_moves::<2>("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2");
+        let report = solve_with_knots(2, &moves, true, FollowRule::Classic);
+        assert_eq!(report.tail_visits, 13);
+        assert_eq!(report.steps, 24);
+        let stats = report.knot_stats.unwrap();
+        let (head, tail) = (&stats[0], &stats[1]);
+        assert_eq!(head.visited_positions.len(), 21);
+        assert_eq!(head.distance_travelled, 24.0);
+        assert_eq!(head.bounding_box, ([0, 0], [5, 4]));
+        assert_eq!(head.first_moved_at_step, Some(1));
+        assert_eq!(tail.visited_positions.len(), 13);
+        assert_eq!(tail.bounding_box, ([0, 0], [4, 4]));
+        assert_eq!(tail.first_moved_at_step, Some(2));
+
+        let report = solve_with_knots(10, &moves, true, FollowRule::Classic);
+        assert_eq!(report.tail_visits, 1);
+        let stats = report.knot_stats.unwrap();
+        assert_eq!(stats[9].visited_positions.len(), 1);
+        assert_eq!(stats[9].distance_travelled, 0.0);
+        assert_eq!(stats[9].bounding_box, ([0, 0], [0, 0]));
+        assert_eq!(stats[9].first_moved_at_step, None);
+        assert_eq!(stats[4].first_moved_at_step, Some(6));
+    }
+
+    #[test]
+    fn replayed_states_match_rebuilt_states() {
+        let mut state = RopeState::new(10, false, FollowRule::Classic).with_trace();
+        for mov in &parse_moves::<2>(DIAGONAL_EXAMPLE) {
+            state.apply_move(*mov);
+        }
+        let trace = state.trace.unwrap();
+        let mut steps = 0;
+        trace.for_each_state(|state| {
+            let rebuilt = trace.state_at(state.steps);
+            assert_eq!(state.knots, rebuilt.knots);
+            assert_eq!(state.visited_tail_positions, rebuilt.visited_tail_positions);
+            steps += 1;
+        });
+        assert_eq!(steps, trace.frames.len());
+        RopeTrace::<2> { frames: Vec::new() }.for_each_state(|_| panic!("no states expected"));
+    }
 }